@@ -0,0 +1,403 @@
+use std::collections::BTreeMap;
+
+/// A tile read back from assembly source.
+#[derive(Clone, Debug)]
+pub struct AsmTile {
+    /// Tile number, from the address its bytes were assembled at.
+    pub index: usize,
+    /// The label on its first byte, if any.
+    pub label: Option<String>,
+    pub bytes: Vec<u8>,
+}
+
+const PAST_END: &str = "The data runs past the highest address there is.";
+
+/// Labels an export puts on data that is not tiles: the palette and the tables written after them.
+const TABLE_LABELS: [&str; 4] = ["Palette", "Colors", "Attributes", "Inks"];
+
+/// Reads the byte data in assembly source back into tiles of `tile_bytes` bytes.
+///
+/// Understands every `export::Syntax` (`.org $05 * 16`, `Tile_0x05:`, `.db %01010101`, `dc.l`, GNU as
+/// `.word` and C arrays) as well as the usual spellings in other assemblers: `org`, `*=`, `db`, `.byte`,
+/// `dc.b`, `!byte`, `defb`, `.dw`, strings, comma separated values in hex, binary or decimal, and labels
+/// with or without a colon. Other directives and instructions are skipped. Instructions take up bytes
+/// that are not counted, so data after them is skipped too until an `.org` says where it goes. Data
+/// under a label ending in `Palette`, `Colors`, `Attributes` or `Inks` is not tiles and is skipped.
+pub fn parse_patterns(text: &str, tile_bytes: usize) -> Result<Vec<AsmTile>, String> {
+    let mut output = Output { address: Some(0), bytes: BTreeMap::new(), labels: BTreeMap::new(), skipping: false };
+    // GNU as makes `.word` 32 bits. It is told apart from other assemblers by its `.section` directives.
+    let mut gas = false;
+    // The element size of the C array being read.
+    let mut c_array: Option<usize> = None;
+
+    for (n, line) in text.lines().enumerate() {
+        let error = |e: String| format!("Line {}: {}", n + 1, e);
+        let mut rest = strip_comment(line);
+
+        if let Some(size) = c_array {
+            let values = match rest.find('}') {
+                Some(i) => {
+                    c_array = None;
+                    &rest[..i]
+                }
+                None => rest,
+            };
+            output.values(values, size, false).map_err(error)?;
+            continue;
+        }
+        if let Some((name, size, values)) = c_declaration(rest) {
+            output.label(name);
+            match values.find('}') {
+                Some(i) => output.values(&values[..i], size, false).map_err(error)?,
+                None => {
+                    output.values(values, size, false).map_err(error)?;
+                    c_array = Some(size);
+                }
+            }
+            continue;
+        }
+
+        // A leading word is a label when it ends in a colon, or sits at the margin and is not a directive.
+        // One at the margin is dropped again if an instruction follows, as in `lda #0`.
+        let at_margin = !rest.starts_with(char::is_whitespace);
+        let (word, tail) = split_word(rest.trim_start());
+        let mut margin_label = None;
+        if let Some(label) = word.strip_suffix(':') {
+            output.label(label);
+            rest = tail;
+        } else if at_margin && !word.is_empty() && !is_directive(word) && !defines_constant(tail) {
+            margin_label = Some(word);
+            rest = tail;
+        }
+
+        let rest = rest.trim();
+        if let Some(value) = rest.strip_prefix("*=").or_else(|| rest.strip_prefix('*').and_then(|x| x.trim_start().strip_prefix('='))) {
+            output.org(eval(value).map_err(error)?);
+            continue;
+        }
+
+        let (word, args) = split_word(rest);
+        let word = word.to_lowercase();
+        let size = match word.as_str() {
+            ".db" | "db" | ".byte" | "byte" | "dc.b" | ".dc.b" | "!byte" | "!by" | "defb" | ".defb" | "fcb" => Some((1, false)),
+            ".word" | "word" if gas => Some((4, false)),
+            ".dw" | "dw" | ".word" | "word" | ".hword" | ".2byte" | "!word" | "!wo" | "defw" | ".defw" => Some((2, false)),
+            ".long" | ".4byte" | ".dword" => Some((4, false)),
+            "dc.w" | ".dc.w" => Some((2, true)),
+            "dc.l" | ".dc.l" => Some((4, true)),
+            _ => None,
+        };
+        if let Some(label) = margin_label {
+            if size.is_some() || word.is_empty() || word.starts_with(['.', '!']) || defines_constant(args) {
+                output.label(label);
+            }
+        }
+
+        if let Some((size, big_endian)) = size {
+            output.values(args, size, big_endian).map_err(error)?;
+            continue;
+        }
+        match word.as_str() {
+            "" => {}
+            ".org" | "org" => output.org(eval(args).map_err(error)?),
+            ".section" => gas = true,
+            "dcb.b" | "dcb.w" | "dcb.l" | ".space" | ".skip" | ".res" | ".ds" | "ds" | ".dsb" | "ds.b" | "!fill" => {
+                // Reserved space is a gap, not tiles, whatever it is filled with.
+                let unit = match word.as_str() {
+                    "dcb.w" => 2,
+                    "dcb.l" => 4,
+                    _ => 1,
+                };
+                let count = args.split(',').next().unwrap_or("");
+                output.address = match eval(count) {
+                    Ok(count) => match output.address {
+                        Some(address) => Some(count.checked_mul(unit).and_then(|x| address.checked_add(x)).ok_or_else(|| error(String::from(PAST_END)))?),
+                        None => None,
+                    },
+                    Err(_) if mentions_symbol(count) => None,
+                    Err(e) => return Err(error(e)),
+                };
+            }
+            // Included files and the like take up space that cannot be worked out here.
+            ".incbin" | "incbin" | "!binary" | "!bin" | ".import" | ".include" | "include" | "!source" | "!src" => output.address = None,
+            x if x.starts_with(['.', '!', '#']) => {}
+            _ if defines_constant(args) => {}
+            // Anything else is an instruction.
+            _ => output.address = None,
+        }
+    }
+
+    let mut tiles: BTreeMap<usize, Vec<u8>> = BTreeMap::new();
+    for (&address, &byte) in output.bytes.iter() {
+        tiles.entry(address / tile_bytes).or_default().push(byte);
+    }
+
+    tiles.into_iter().map(|(index, bytes)| {
+        if bytes.len() != tile_bytes {
+            return Err(format!("Tile {:#04x} has {} of its {} bytes.", index, bytes.len(), tile_bytes));
+        }
+        let label = output.labels.get(&(index * tile_bytes)).cloned();
+        Ok(AsmTile { index, label, bytes })
+    }).collect()
+}
+
+/// Where assembled bytes have got to.
+struct Output {
+    /// `None` after instructions or included files, whose size is not known.
+    address: Option<usize>,
+    bytes: BTreeMap<usize, u8>,
+    /// The last label put on each address.
+    labels: BTreeMap<usize, String>,
+    /// Whether the data under the current label is skipped, as palettes and tables are.
+    /// Data is also skipped while `address` is unknown.
+    skipping: bool,
+}
+
+impl Output {
+    fn org(&mut self, address: usize) {
+        self.address = Some(address);
+        self.skipping = false;
+    }
+
+    fn label(&mut self, name: &str) {
+        self.skipping = TABLE_LABELS.iter().any(|x| name.ends_with(x));
+        if let (Some(address), false) = (self.address, self.skipping) {
+            self.labels.insert(address, String::from(name));
+        }
+    }
+
+    /// Assembles comma separated values of `size` bytes each. Strings are a byte to a character.
+    fn values(&mut self, args: &str, size: usize, big_endian: bool) -> Result<(), String> {
+        for arg in split_args(args) {
+            let arg = arg.trim();
+            let quoted = arg.len() >= 2 && ((arg.starts_with('"') && arg.ends_with('"')) || (arg.starts_with('\'') && arg.ends_with('\'')));
+            if quoted {
+                for c in arg[1..arg.len() - 1].bytes() {
+                    self.value(Some(c as usize), size, big_endian)?;
+                }
+                continue;
+            }
+            match eval(arg) {
+                Ok(value) if value as u64 >> (size * 8) != 0 => return Err(format!("{} does not fit in {} bytes.", arg, size)),
+                Ok(value) => self.value(Some(value), size, big_endian)?,
+                // Addresses of labels and such are not tiles, so they only take up room.
+                Err(_) if mentions_symbol(arg) => self.value(None, size, big_endian)?,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    fn value(&mut self, value: Option<usize>, size: usize, big_endian: bool) -> Result<(), String> {
+        let end = match self.address {
+            Some(address) => Some(address.checked_add(size).ok_or(PAST_END)?),
+            None => None,
+        };
+        let (address, value) = match (self.address, value) {
+            (Some(address), Some(value)) if !self.skipping => (address, value),
+            _ => {
+                self.address = end;
+                return Ok(());
+            }
+        };
+        for i in 0..size {
+            let shift = if big_endian { size - 1 - i } else { i } * 8;
+            if self.bytes.insert(address + i, (value >> shift) as u8).is_some() {
+                return Err(format!("${:04x} is written twice.", address + i));
+            }
+        }
+        self.address = end;
+        Ok(())
+    }
+}
+
+/// Reads a C array declaration such as `const unsigned int Tiles[12] = {` as its name, element size and
+/// whatever follows the brace.
+fn c_declaration(line: &str) -> Option<(&str, usize, &str)> {
+    let line = line.trim();
+    let (declaration, values) = line.split_once('{')?;
+    let (declaration, _) = declaration.split_once('[')?;
+    if !declaration.starts_with("const ") && !declaration.starts_with("static ") && !declaration.starts_with("unsigned ") {
+        return None;
+    }
+    let mut words = declaration.split_whitespace().rev();
+    let name = words.next()?;
+    let size = words.map(|x| match x {
+        "char" | "uint8_t" | "u8" => 1,
+        "short" | "uint16_t" | "u16" => 2,
+        "int" | "long" | "uint32_t" | "u32" => 4,
+        _ => 0,
+    }).max()?;
+    if size == 0 {
+        return None;
+    }
+    Some((name, size, values))
+}
+
+/// Splits values on commas that are not inside quotes.
+fn split_args(args: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in args.char_indices() {
+        match (c, quote) {
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            (',', None) => {
+                result.push(&args[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    result.push(&args[start..]);
+    result.into_iter().filter(|x| !x.trim().is_empty()).collect()
+}
+
+/// Whether an expression names something other than numbers, such as a label.
+fn mentions_symbol(text: &str) -> bool {
+    text.split(['+', '-', '*', '/', '<', '>', '(', ')', '#']).map(str::trim).any(|x| x.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == '@' || c == '.') && parse_number(x).is_err())
+}
+
+/// Cuts off `;` and `//` comments, and GNU as `@` ones. A `@` starting a word, as in ca65's `@loop`, is kept.
+fn strip_comment(line: &str) -> &str {
+    let at = line.match_indices('@').map(|(i, _)| i).find(|&i| line[i + 1..].starts_with(char::is_whitespace) || i + 1 == line.len());
+    let end = [line.find(';'), line.find("//"), at].iter().flatten().min().copied().unwrap_or(line.len());
+    &line[..end]
+}
+
+fn split_word(text: &str) -> (&str, &str) {
+    match text.find(char::is_whitespace) {
+        Some(i) => (&text[..i], &text[i..]),
+        None => (text, ""),
+    }
+}
+
+fn is_directive(word: &str) -> bool {
+    word.starts_with('.') || word.starts_with('!') || word.starts_with('*')
+        || ["org", "db", "byte", "dc.b", "defb", "fcb", "dw", "word", "dc.w", "dc.l", "defw", "dcb.b", "dcb.w", "dcb.l", "ds", "ds.b", "incbin", "include"].contains(&word.to_lowercase().as_str())
+}
+
+/// Whether what follows a name makes it a constant, as in `PPU_CTRL = $2000` or `SIZE equ 16`.
+fn defines_constant(tail: &str) -> bool {
+    let tail = tail.trim_start();
+    tail.starts_with('=') || ["equ", ".equ", "set", ".set"].contains(&split_word(tail).0.to_lowercase().as_str())
+}
+
+/// Works out a constant expression of numbers joined by `+`, `-`, `*` and `/`.
+fn eval(text: &str) -> Result<usize, String> {
+    let mut total: i64 = 0;
+    let mut sign = 1;
+    let mut term = String::new();
+
+    for c in text.chars().chain(std::iter::once('+')) {
+        match c {
+            '+' | '-' if !term.trim().is_empty() => {
+                let value = eval_term(&term)?.checked_mul(sign);
+                total = value.and_then(|x| total.checked_add(x)).ok_or(format!("{} is too large.", text.trim()))?;
+                sign = if c == '-' { -1 } else { 1 };
+                term.clear();
+            }
+            '-' => sign = -sign,
+            '+' => {}
+            _ => term.push(c),
+        }
+    }
+
+    if total < 0 {
+        return Err(format!("{} is negative.", text.trim()));
+    }
+    Ok(total as usize)
+}
+
+fn eval_term(text: &str) -> Result<i64, String> {
+    let mut parts = text.split(['*', '/']);
+    let mut result = parse_number(parts.next().unwrap_or(""))?;
+    let mut rest = text;
+    for part in parts {
+        let op = rest.find(['*', '/']).unwrap();
+        let value = parse_number(part)?;
+        result = match &rest[op..op + 1] {
+            "*" => result.checked_mul(value),
+            _ if value == 0 => return Err(String::from("Division by zero.")),
+            _ => result.checked_div(value),
+        }.ok_or(format!("{} is too large.", text.trim()))?;
+        rest = &rest[op + 1..];
+    }
+    Ok(result)
+}
+
+/// Reads `$ff`, `0xff`, `0ffh`, `%11111111`, `0b11111111` or `255`.
+fn parse_number(text: &str) -> Result<i64, String> {
+    let text = text.trim();
+    let lower = text.to_lowercase();
+    let result = if let Some(hex) = lower.strip_prefix('$').or_else(|| lower.strip_prefix("0x")) {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(bin) = lower.strip_prefix('%').or_else(|| lower.strip_prefix("0b")) {
+        i64::from_str_radix(bin, 2)
+    } else if let Some(hex) = lower.strip_suffix('h') {
+        i64::from_str_radix(hex, 16)
+    } else {
+        lower.parse::<i64>()
+    };
+    result.map_err(|_| format!("\"{}\" is not a number.", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chr::{ColorFormat, TileFormat};
+    use crate::export::{self, Export, Syntax, Table};
+
+    fn sheet(format: TileFormat) -> Export {
+        let tiles = (0..3).map(|i| (i, (0..format.tile_bytes()).map(|x| (x * 37 + i * 11) as u8).collect())).collect();
+        Export { tiles, ..Default::default() }
+    }
+
+    fn parsed(text: &str, tile_bytes: usize) -> BTreeMap<usize, Vec<u8>> {
+        parse_patterns(text, tile_bytes).unwrap().into_iter().map(|x| (x.index, x.bytes)).collect()
+    }
+
+    #[test]
+    fn reads_every_syntax() {
+        for format in [TileFormat::Nes, TileFormat::Genesis] {
+            let mut export = sheet(format);
+            export.palette = Some((ColorFormat::Genesis, vec![[0, 0, 0], [0xff, 0x80, 0x20]]));
+            export.tables.push(Table { name: "Colors", bytes: vec![1, 2, 3], extension: "col" });
+            for syntax in Syntax::ALL {
+                let text = export::text(&export, format, syntax, "Tile_").unwrap();
+                assert_eq!(parsed(&text, format.tile_bytes()), export.tiles, "{:?} {:?}", format, syntax);
+            }
+        }
+    }
+
+    #[test]
+    fn labels_tiles() {
+        let text = export::text(&sheet(TileFormat::Nes), TileFormat::Nes, Syntax::Dc, "Font_").unwrap();
+        let labels = parse_patterns(&text, 16).unwrap().into_iter().map(|x| x.label).collect::<Vec<Option<String>>>();
+        assert_eq!(labels, [Some(String::from("Font_0x00")), Some(String::from("Font_0x01")), Some(String::from("Font_0x02"))]);
+    }
+
+    #[test]
+    fn skips_instructions() {
+        let text = "Reset:\n        lda #0\n        sta $2000\nloop    jmp loop\n        .word Reset\n        .org $10\n        .byte \"ABCDEFGH\", 1, 2, 3, 4, 5, 6, 7, 8\n";
+        let tiles = parse_patterns(text, 16).unwrap();
+        assert_eq!(tiles.len(), 1);
+        assert_eq!(tiles[0].index, 1);
+        assert_eq!(tiles[0].label, None);
+        assert_eq!(&tiles[0].bytes[..4], b"ABCD");
+    }
+
+    #[test]
+    fn rejects_overflowing_addresses() {
+        assert_eq!(parse_patterns("        .org $7fffffffffffffff + 1\n", 16).unwrap_err(), "Line 1: $7fffffffffffffff + 1 is too large.");
+        assert_eq!(parse_patterns("        .org 2 * $7fffffffffffffff\n", 16).unwrap_err(), "Line 1: 2 * $7fffffffffffffff is too large.");
+        assert!(parse_patterns("        .org $7fffffffffffffff\n        .res $7fffffffffffffff\n        .res $7fffffffffffffff\n        .db 1\n", 16).is_err());
+    }
+
+    #[test]
+    fn rejects_overlapping_blocks() {
+        let text = "        .org 0\n        .db 1, 2\n        .org 1\n        .db 3\n";
+        assert_eq!(parse_patterns(text, 16).unwrap_err(), "Line 4: $0001 is written twice.");
+    }
+}
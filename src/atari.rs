@@ -0,0 +1,51 @@
+use std::f32::consts::PI;
+use crate::chr;
+
+/// The 128 colours of the Atari 8-bit computers as register values, hue in the high nibble and
+/// luminance in bits 1 to 3, with an NTSC approximation of each. Hue 0 is grey.
+fn palette() -> Vec<(u8, [u8; 3])> {
+    let mut colors = Vec::new();
+    for hue in 0..16u8 {
+        for lum in 0..8u8 {
+            let y = 0.1 + lum as f32 * 0.12;
+            let (i, q) = match hue {
+                0 => (0.0, 0.0),
+                _ => {
+                    let angle = (hue - 1) as f32 * 2.0 * PI / 15.0 + PI;
+                    (0.2 * angle.cos(), 0.2 * angle.sin())
+                }
+            };
+            let rgb = [y + 0.956 * i + 0.621 * q, y - 0.272 * i - 0.647 * q, y - 1.106 * i + 1.703 * q];
+            colors.push((hue << 4 | lum << 1, rgb.map(|x| (x.clamp(0.0, 1.0) * 255.0) as u8)));
+        }
+    }
+    colors
+}
+
+/// The Atari colour register value closest to an RGB colour.
+pub fn nearest(color: [u8; 3]) -> u8 {
+    chr::nearest(palette(), color)
+}
+
+/// The colour register values for a palette in ANTIC mode 4 order: COLBK, then COLPF0 to COLPF2,
+/// ready to store at $02c8 and $02c4 to $02c6.
+pub fn registers(palette: &[[u8; 3]]) -> Result<Vec<u8>, String> {
+    chr::distinct(palette.iter().map(|&x| nearest(x)).collect(), |x| format!("Atari color ${:02x}", x))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn greys() {
+        assert_eq!(nearest([0x00, 0x00, 0x00]), 0x00);
+        assert_eq!(nearest([0xff, 0xff, 0xff]), 0x0e);
+    }
+
+    #[test]
+    fn registers_must_differ() {
+        assert_eq!(registers(&[[0x00, 0x00, 0x00], [0xff, 0xff, 0xff]]), Ok(vec![0x00, 0x0e]));
+        assert!(registers(&[[0x00, 0x00, 0x00], [0x08, 0x08, 0x08]]).is_err());
+    }
+}
@@ -0,0 +1,44 @@
+//! The editor's command line tools on their own, so they build without the `gui` feature.
+
+use std::fs;
+use std::path::Path;
+use lucifer_tile_editor::chr::TileFormat;
+use lucifer_tile_editor::compress::Compression;
+use lucifer_tile_editor::{convert, verify};
+
+fn main() {
+    let args = std::env::args().collect::<Vec<String>>();
+    let code = match (args.get(1).map(|x| x.as_str()), args.len()) {
+        (Some("--verify"), 3..=5) => verify::run_cli(&args[2], args.get(3).map(|x| x.as_str()), args.get(4).map(|x| x.as_str())),
+        (Some("--convert"), 6) => convert_cli(&args[2], &args[3], &args[4], &args[5]),
+        _ => {
+            let formats = TileFormat::ALL.iter().map(|x| format!("{:?}", x).to_lowercase()).collect::<Vec<String>>();
+            let compressions = Compression::ALL.iter().map(|x| format!("{:?}", x).to_lowercase()).collect::<Vec<String>>();
+            eprintln!("Usage: {} --verify <project.ltp> [<exported .asm, .chr or .nes> [<compression of a .chr>]]", args[0]);
+            eprintln!("       {} --convert <picture.png> <config or project.ltp> <format> <output>", args[0]);
+            eprintln!("Formats: {}", formats.join(", "));
+            eprintln!("Compressions: {}", compressions.join(", "));
+            2
+        }
+    };
+    std::process::exit(code);
+}
+
+/// Converts the tiles a config assigns in a picture to `format`, named as in `TileFormat` ("nes",
+/// "gba4" and so on), and writes them to `output`. Returns the exit code: 0 on success, 2 otherwise.
+fn convert_cli(image: &str, config: &str, format: &str, output: &str) -> i32 {
+    let result = TileFormat::ALL.iter().find(|x| format!("{:?}", x).eq_ignore_ascii_case(format))
+        .ok_or(format!("Unknown format \"{}\".", format))
+        .and_then(|&format| convert::convert_files(Path::new(image), Path::new(config), format))
+        .and_then(|data| fs::write(output, &data).map(|_| data.len()).map_err(|e| format!("Could not write {}: {}", output, e)));
+    match result {
+        Ok(n) => {
+            println!("Wrote {} bytes to {}.", n, output);
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            2
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::{BTreeMap, BTreeSet};
+use crate::chr::{self, TileFormat};
+
+/// The sixteen VIC-II colours, as measured by Pepto.
+pub const PALETTE: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00], [0xff, 0xff, 0xff], [0x68, 0x37, 0x2b], [0x70, 0xa4, 0xb2],
+    [0x6f, 0x3d, 0x86], [0x58, 0x8d, 0x43], [0x35, 0x28, 0x79], [0xb8, 0xc7, 0x6f],
+    [0x6f, 0x4f, 0x25], [0x43, 0x39, 0x00], [0x9a, 0x67, 0x59], [0x44, 0x44, 0x44],
+    [0x6c, 0x6c, 0x6c], [0x9a, 0xd2, 0x84], [0x6c, 0x5e, 0xb5], [0x95, 0x95, 0x95],
+];
+
+/// A charset ready to encode: the tiles as colour indices and the colours the indices stand for.
+#[derive(Clone, Debug)]
+pub struct Charset {
+    pub tiles: BTreeMap<usize, [u8; 64]>,
+    /// The colour of $d021, shared by every character.
+    pub background: u8,
+    /// The colours of $d022 and $d023, for multicolour charsets.
+    pub multicolor: Option<[u8; 2]>,
+    /// The colour RAM value of each character. Multicolour characters have bit 3 set.
+    pub colors: BTreeMap<usize, u8>,
+}
+
+/// The VIC-II colour closest to an RGB colour.
+pub fn nearest(color: [u8; 3]) -> u8 {
+    chr::nearest((0..16).zip(PALETTE.iter().copied()), color)
+}
+
+/// Checks RGBA tiles, keyed by tile number, against what a hires or multicolour character can show
+/// and numbers their pixels. The colours most tiles use become the shared background and multicolour
+/// registers; each character may add one colour of its own, which in multicolour must be one of the first eight.
+pub fn index(tiles: &BTreeMap<usize, Vec<u8>>, format: TileFormat) -> Result<Charset, String> {
+    let multi = format == TileFormat::C64Multi;
+    let pixels = tiles.iter().map(|(&i, tile)| (i, tile.chunks(4).map(|x| nearest([x[0], x[1], x[2]])).collect::<Vec<u8>>())).collect::<BTreeMap<usize, Vec<u8>>>();
+
+    // Rank colours by how many tiles use them.
+    let mut usage = [0usize; 16];
+    for tile in pixels.values() {
+        for &c in tile.iter().collect::<BTreeSet<&u8>>() {
+            usage[c as usize] += 1;
+        }
+    }
+    let mut ranked = (0..16u8).collect::<Vec<u8>>();
+    ranked.sort_by_key(|&c| std::cmp::Reverse(usage[c as usize]));
+    let shared = if multi { ranked[0..3].to_vec() } else { ranked[0..1].to_vec() };
+
+    let mut errors = Vec::new();
+    let mut charset = Charset {
+        tiles: BTreeMap::new(),
+        background: shared[0],
+        multicolor: if multi { Some([shared[1], shared[2]]) } else { None },
+        colors: BTreeMap::new(),
+    };
+
+    for (&i, tile) in pixels.iter() {
+        if format.wide() && (0..32).any(|x| tile[x * 2] != tile[x * 2 + 1]) {
+            errors.push(format!("{:#04x} has pixels that are not in matching pairs", i));
+            continue;
+        }
+
+        let own = tile.iter().filter(|c| !shared.contains(c)).collect::<BTreeSet<&u8>>();
+        let own = match own.len() {
+            0 => 0,
+            1 => **own.iter().next().unwrap(),
+            n => {
+                errors.push(format!("{:#04x} has {} colors besides the shared ones, 1 is allowed", i, n));
+                continue;
+            }
+        };
+        if multi && own >= 8 {
+            errors.push(format!("{:#04x} uses color {} but multicolor characters can only add colors 0 to 7", i, own));
+            continue;
+        }
+
+        let mut indices = [0; 64];
+        for (j, c) in tile.iter().enumerate() {
+            indices[j] = match shared.iter().position(|x| x == c) {
+                Some(x) => x as u8,
+                None if multi => 3,
+                None => 1,
+            };
+        }
+        charset.tiles.insert(i, indices);
+        charset.colors.insert(i, if multi { own | 8 } else { own });
+    }
+
+    if !errors.is_empty() {
+        let n = errors.len();
+        errors.truncate(8);
+        if n > errors.len() {
+            errors.push(format!("and {} more", n - errors.len()));
+        }
+        return Err(format!("{} tiles cannot be {} characters: {}.", n, format.name(), errors.join("; ")));
+    }
+    Ok(charset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An RGBA tile whose first row holds the VIC-II colours `row` and the rest `fill`.
+    fn tile(row: [u8; 8], fill: u8) -> Vec<u8> {
+        (0..64).flat_map(|i| {
+            let [r, g, b] = PALETTE[if i < 8 { row[i] } else { fill } as usize];
+            vec![r, g, b, 0xff]
+        }).collect()
+    }
+
+    #[test]
+    fn nearest_colors() {
+        assert_eq!(nearest([0x00, 0x00, 0x00]), 0);
+        assert_eq!(nearest([0xff, 0xff, 0xff]), 1);
+        assert_eq!(nearest([0x6c, 0x5e, 0xb0]), 14);
+    }
+
+    #[test]
+    fn hires() {
+        // Black is in both tiles, so it is the background and white is the first tile's own colour.
+        let tiles = [(0, tile([1, 1, 1, 1, 0, 0, 0, 0], 0)), (1, tile([0; 8], 0))].iter().cloned().collect();
+        let charset = index(&tiles, TileFormat::C64Hires).unwrap();
+        assert_eq!(charset.background, 0);
+        assert_eq!(charset.multicolor, None);
+        assert_eq!(charset.colors[&0], 1);
+        assert_eq!(TileFormat::C64Hires.encode(&charset.tiles[&0]), [0xf0, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn multicolor() {
+        // Black, blue and light blue are in both tiles; red is the first tile's own colour, with bit 3 set.
+        let tiles = [(0, tile([0, 0, 6, 6, 14, 14, 2, 2], 0)), (1, tile([0, 0, 6, 6, 14, 14, 0, 0], 0))].iter().cloned().collect();
+        let charset = index(&tiles, TileFormat::C64Multi).unwrap();
+        assert_eq!(charset.background, 0);
+        assert_eq!(charset.multicolor, Some([6, 14]));
+        assert_eq!(charset.colors[&0], 2 | 8);
+        assert_eq!(TileFormat::C64Multi.encode(&charset.tiles[&0]), [0x1b, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn one_color_per_cell() {
+        let tiles = [(0, tile([1, 1, 2, 2, 0, 0, 0, 0], 0)), (1, tile([0; 8], 0))].iter().cloned().collect();
+        let error = index(&tiles, TileFormat::C64Hires).unwrap_err();
+        assert!(error.contains("0x00 has 2 colors besides the shared ones"), "{}", error);
+
+        let tiles = [(0, tile([0, 0, 6, 6, 14, 14, 8, 8], 0)), (1, tile([0, 0, 6, 6, 14, 14, 0, 0], 0))].iter().cloned().collect();
+        let error = index(&tiles, TileFormat::C64Multi).unwrap_err();
+        assert!(error.contains("0x00 uses color 8"), "{}", error);
+
+        let tiles = [(0, tile([0, 6, 6, 6, 14, 14, 0, 0], 0))].iter().cloned().collect();
+        let error = index(&tiles, TileFormat::C64Multi).unwrap_err();
+        assert!(error.contains("0x00 has pixels that are not in matching pairs"), "{}", error);
+    }
+}
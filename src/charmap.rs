@@ -0,0 +1,135 @@
+use std::collections::{BTreeMap, BTreeSet};
+use crate::convert::parse_index;
+
+/// Writes a charmap for a project file as `tile:utf8 hex` pairs, so any text survives the `key=value` format.
+pub fn to_project(charmap: &BTreeMap<usize, String>) -> String {
+    charmap.iter().map(|(k, text)| {
+        let hex = text.bytes().map(|x| format!("{:02x}", x)).collect::<String>();
+        format!("{:#04x}:{}", k, hex)
+    }).collect::<Vec<String>>().join(",")
+}
+
+pub fn from_project(text: &str) -> Option<BTreeMap<usize, String>> {
+    let mut charmap = BTreeMap::new();
+    for pair in text.split(',').filter(|x| !x.trim().is_empty()) {
+        let (k, hex) = pair.trim().split_once(':')?;
+        let bytes = (0..hex.len()).step_by(2).map(|i| hex.get(i..i + 2).and_then(|x| u8::from_str_radix(x, 16).ok())).collect::<Option<Vec<u8>>>()?;
+        charmap.insert(parse_index(k)?, String::from_utf8(bytes).ok()?);
+    }
+    Some(charmap)
+}
+
+/// How text is encoded to reach a tile. A single ASCII character is its own code. Anything else,
+/// a longer string or a character outside ASCII, cannot be written as one byte of source, so it
+/// gets a code from $80 up and a constant `{prefix}Text_{name}` to write it with.
+struct Code<'a> {
+    code: u8,
+    tile: u8,
+    text: &'a str,
+    constant: Option<String>,
+}
+
+fn codes<'a>(charmap: &'a BTreeMap<usize, String>, prefix: &str) -> Result<Vec<Code<'a>>, String> {
+    let mut codes = Vec::new();
+    let mut constants = BTreeSet::new();
+    let mut next = 0x80;
+    for (&tile, text) in charmap.iter() {
+        if tile > 0xff {
+            return Err(format!("Tile {:#04x} does not fit in a byte.", tile));
+        }
+        let mut chars = text.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            if c.is_ascii() {
+                codes.push(Code { code: c as u8, tile: tile as u8, text, constant: None });
+                continue;
+            }
+        }
+
+        if next > 0xff {
+            return Err(String::from("Only 128 strings fit in the codes from $80 to $ff."));
+        }
+        let name = text.chars().map(|x| if x.is_ascii_alphanumeric() { x } else { '_' }).collect::<String>();
+        let mut constant = format!("{}Text_{}", prefix, name.trim_matches('_'));
+        if name.trim_matches('_').is_empty() || constants.contains(&constant) {
+            constant = format!("{}Text_{:02x}", prefix, tile);
+        }
+        constants.insert(constant.clone());
+        codes.push(Code { code: next as u8, tile: tile as u8, text, constant: Some(constant) });
+        next += 1;
+    }
+    Ok(codes)
+}
+
+/// Writes a ca65 `.charmap` block mapping each ASCII character to its tile number. ca65 maps single
+/// bytes of source, so strings and other characters become constants holding their tile number
+/// instead, to put in text as `.byte "Press ", Tile_Text_A`.
+pub fn ca65(charmap: &BTreeMap<usize, String>, prefix: &str) -> Result<String, String> {
+    let mut text = String::from("; Character map\n");
+    for code in codes(charmap, prefix)? {
+        match &code.constant {
+            None => text.push_str(&format!("        .charmap ${:02x}, ${:02x} ; {:?}\n", code.code, code.tile, code.text)),
+            Some(constant) => text.push_str(&format!("        {} = ${:02x} ; {:?}\n", constant, code.tile, code.text)),
+        }
+    }
+    Ok(text)
+}
+
+/// Writes a table indexed by character code that gives each character's tile number, 0 where there
+/// is none. Strings and characters outside ASCII take codes from $80 up, named by constants.
+pub fn lookup_table(charmap: &BTreeMap<usize, String>, prefix: &str) -> Result<String, String> {
+    let mut table = vec![0u8; 128];
+    let mut text = String::from("; Character to tile lookup table\n");
+    for code in codes(charmap, prefix)? {
+        if code.code as usize >= table.len() {
+            table.resize(code.code as usize + 1, 0);
+        }
+        table[code.code as usize] = code.tile;
+        if let Some(constant) = &code.constant {
+            text.push_str(&format!("        {} = ${:02x} ; {:?} is tile ${:02x}\n", constant, code.code, code.text, code.tile));
+        }
+    }
+
+    text.push_str(&format!("\n        {}Charmap:", prefix));
+    for row in table.chunks(16) {
+        let bytes = row.iter().map(|x| format!("${:02x}", x)).collect::<Vec<String>>();
+        text.push_str(&format!("\n        .db     {}", bytes.join(", ")));
+    }
+    text.push('\n');
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn charmap() -> BTreeMap<usize, String> {
+        [(0x20, "A"), (0x21, "é"), (0x22, "<3"), (0x23, "ok")].iter().map(|&(k, x)| (k, String::from(x))).collect()
+    }
+
+    #[test]
+    fn maps_strings_to_constants() {
+        let text = ca65(&charmap(), "Font_").unwrap();
+        assert!(text.contains(".charmap $41, $20 ; \"A\"\n"));
+        assert!(text.contains("Font_Text_21 = $21 ; \"é\"\n"));
+        assert!(text.contains("Font_Text_3 = $22 ; \"<3\"\n"));
+        assert!(text.contains("Font_Text_ok = $23 ; \"ok\"\n"));
+        assert!(!text.contains(".charmap $e9"));
+    }
+
+    #[test]
+    fn looks_strings_up_past_ascii() {
+        let text = lookup_table(&charmap(), "Font_").unwrap();
+        assert!(text.contains("Font_Text_ok = $82 ; \"ok\" is tile $23\n"));
+        let rows = text.lines().filter(|x| x.contains(".db")).collect::<Vec<&str>>();
+        assert_eq!(rows.len(), 9);
+        assert!(rows[4].starts_with("        .db     $00, $20,"));
+        assert_eq!(rows[8], "        .db     $21, $22, $23");
+    }
+
+    #[test]
+    fn round_trips_projects() {
+        let mut charmap = charmap();
+        charmap.insert(0x100, String::from("z"));
+        assert_eq!(from_project(&to_project(&charmap)), Some(charmap));
+    }
+}
@@ -0,0 +1,481 @@
+/// Colours to show decoded tiles with, since pattern data carries no palette.
+/// Each is in ascending order of red, so sorting a tile's colours gives back its indices.
+pub const PREVIEW_PALETTES: [(&str, [[u8; 3]; 4]); 3] = [
+    ("Greyscale", [[0x00, 0x00, 0x00], [0x55, 0x55, 0x55], [0xaa, 0xaa, 0xaa], [0xff, 0xff, 0xff]]),
+    ("Game Boy", [[0x0f, 0x38, 0x0f], [0x30, 0x62, 0x30], [0x8b, 0xac, 0x0f], [0x9b, 0xbc, 0x0f]]),
+    ("NES", [[0x00, 0x00, 0x00], [0x5c, 0x94, 0xfc], [0xc8, 0x4c, 0x0c], [0xfc, 0xbc, 0xb0]]),
+];
+
+/// Ways tile graphics are laid out in memory.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TileFormat {
+    /// NES: the eight rows of the low bitplane, then the eight of the high one.
+    Nes,
+    /// Game Boy: both bitplanes interleaved row by row.
+    GameBoy,
+    /// SNES 4bpp: planes 0 and 1 interleaved like the Game Boy, then planes 2 and 3.
+    Snes4,
+    /// SNES 8bpp: four interleaved pairs of planes, 16 bytes each.
+    Snes8,
+    /// Master System 4bpp: the four bitplane bytes of each row together, 32 bytes a tile.
+    Sms,
+    /// Game Gear: Master System tiles with a 12-bit palette.
+    GameGear,
+    /// Genesis 4bpp: one nibble per pixel, leftmost pixel in the high nibble.
+    Genesis,
+    /// Game Boy Advance 4bpp: one nibble per pixel, leftmost pixel in the low nibble.
+    Gba4,
+    /// Game Boy Advance 8bpp: one byte per pixel.
+    Gba8,
+    /// Commodore 64 hires characters: one bit per pixel, one byte per row.
+    C64Hires,
+    /// Commodore 64 multicolour characters: two bits for each double-wide pixel, one byte per row.
+    C64Multi,
+    /// TMS9918 Graphics II patterns: one bit per pixel, with a colour table byte for each row.
+    Tms9918,
+    /// ZX Spectrum UDGs: one bit per pixel, with an attribute byte for each tile.
+    ZxSpectrum,
+    /// Commander X16 VERA tiles at 1, 2, 4 or 8 bits per pixel, leftmost pixel in the high bits.
+    Vera1,
+    Vera2,
+    Vera4,
+    Vera8,
+    /// Amstrad CPC mode 0: two 4-bit pixels a byte with their bits interleaved, 32 bytes a tile.
+    CpcMode0,
+    /// Amstrad CPC mode 1: four 2-bit pixels a byte, low bits in the high nibble, 16 bytes a tile.
+    CpcMode1,
+    /// Atari ANTIC mode 4 characters: two bits for each double-wide pixel, one byte per row.
+    AtariMode4,
+    /// One bit per pixel, one byte per row.
+    Mono,
+}
+
+impl TileFormat {
+    pub const ALL: [TileFormat; 21] = [TileFormat::Nes, TileFormat::GameBoy, TileFormat::Snes4, TileFormat::Snes8, TileFormat::Sms, TileFormat::GameGear, TileFormat::Genesis, TileFormat::Gba4, TileFormat::Gba8,
+        TileFormat::C64Hires, TileFormat::C64Multi, TileFormat::Tms9918, TileFormat::ZxSpectrum,
+        TileFormat::Vera1, TileFormat::Vera2, TileFormat::Vera4, TileFormat::Vera8,
+        TileFormat::CpcMode0, TileFormat::CpcMode1, TileFormat::AtariMode4, TileFormat::Mono];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TileFormat::Nes => "NES 2bpp planar",
+            TileFormat::GameBoy => "Game Boy 2bpp interleaved",
+            TileFormat::Snes4 => "SNES 4bpp",
+            TileFormat::Snes8 => "SNES 8bpp",
+            TileFormat::Sms => "Master System 4bpp",
+            TileFormat::GameGear => "Game Gear 4bpp",
+            TileFormat::Genesis => "Genesis 4bpp",
+            TileFormat::Gba4 => "GBA 4bpp",
+            TileFormat::Gba8 => "GBA 8bpp",
+            TileFormat::C64Hires => "C64 hires",
+            TileFormat::C64Multi => "C64 multicolor",
+            TileFormat::Tms9918 => "TMS9918 (MSX, ColecoVision)",
+            TileFormat::ZxSpectrum => "ZX Spectrum UDG",
+            TileFormat::Vera1 => "X16 VERA 1bpp",
+            TileFormat::Vera2 => "X16 VERA 2bpp",
+            TileFormat::Vera4 => "X16 VERA 4bpp",
+            TileFormat::Vera8 => "X16 VERA 8bpp",
+            TileFormat::CpcMode0 => "Amstrad CPC mode 0",
+            TileFormat::CpcMode1 => "Amstrad CPC mode 1",
+            TileFormat::AtariMode4 => "Atari ANTIC mode 4",
+            TileFormat::Mono => "1bpp",
+        }
+    }
+
+    fn layout(self) -> Layout {
+        match self {
+            TileFormat::Genesis => Layout::Packed { bits: 4, low_first: false, wide: false },
+            TileFormat::Gba4 => Layout::Packed { bits: 4, low_first: true, wide: false },
+            TileFormat::Gba8 => Layout::Packed { bits: 8, low_first: true, wide: false },
+            TileFormat::C64Multi | TileFormat::AtariMode4 => Layout::Packed { bits: 2, low_first: false, wide: true },
+            TileFormat::Vera1 => Layout::Packed { bits: 1, low_first: false, wide: false },
+            TileFormat::Vera2 => Layout::Packed { bits: 2, low_first: false, wide: false },
+            TileFormat::Vera4 => Layout::Packed { bits: 4, low_first: false, wide: false },
+            TileFormat::Vera8 => Layout::Packed { bits: 8, low_first: false, wide: false },
+            TileFormat::CpcMode0 => Layout::Interleaved(&[7, 3, 5, 1]),
+            TileFormat::CpcMode1 => Layout::Interleaved(&[7, 3]),
+            _ => Layout::Planar(self.planes()),
+        }
+    }
+
+    /// Where the rows of each bitplane are, as (first byte, distance between rows), lowest plane first.
+    fn planes(self) -> &'static [(usize, usize)] {
+        match self {
+            TileFormat::Nes => &[(0, 1), (8, 1)],
+            TileFormat::GameBoy => &[(0, 2), (1, 2)],
+            TileFormat::Snes4 => &[(0, 2), (1, 2), (16, 2), (17, 2)],
+            TileFormat::Snes8 => &[(0, 2), (1, 2), (16, 2), (17, 2), (32, 2), (33, 2), (48, 2), (49, 2)],
+            TileFormat::Sms | TileFormat::GameGear => &[(0, 4), (1, 4), (2, 4), (3, 4)],
+            TileFormat::Mono | TileFormat::C64Hires | TileFormat::Tms9918 | TileFormat::ZxSpectrum => &[(0, 1)],
+            TileFormat::Genesis | TileFormat::Gba4 | TileFormat::Gba8 | TileFormat::C64Multi
+            | TileFormat::Vera1 | TileFormat::Vera2 | TileFormat::Vera4 | TileFormat::Vera8
+            | TileFormat::CpcMode0 | TileFormat::CpcMode1 | TileFormat::AtariMode4 => &[],
+        }
+    }
+
+    pub fn bits(self) -> usize {
+        match self.layout() {
+            Layout::Planar(planes) => planes.len(),
+            Layout::Packed { bits, .. } => bits,
+            Layout::Interleaved(shifts) => shifts.len(),
+        }
+    }
+
+    /// Whether each pixel is stored once for two side by side on screen, which must then match.
+    pub fn wide(self) -> bool {
+        matches!(self.layout(), Layout::Packed { wide: true, .. })
+    }
+
+    pub fn tile_bytes(self) -> usize {
+        match self.layout() {
+            Layout::Packed { wide: true, .. } => self.bits() * 4,
+            _ => self.bits() * 8,
+        }
+    }
+
+    pub fn colors(self) -> usize {
+        1 << self.bits()
+    }
+
+    /// How many bytes make up one row of pixels when they are stored together, so text output can keep them on one line.
+    pub fn row_bytes(self) -> usize {
+        match self {
+            TileFormat::Sms | TileFormat::GameGear | TileFormat::Genesis | TileFormat::Gba4 | TileFormat::Vera4 | TileFormat::CpcMode0 => 4,
+            TileFormat::Gba8 | TileFormat::Vera8 => 8,
+            TileFormat::Vera2 | TileFormat::CpcMode1 => 2,
+            _ => 1,
+        }
+    }
+
+    /// How the hardware stores the colours these tiles index, for formats whose palette is not fixed.
+    pub fn color_format(self) -> Option<ColorFormat> {
+        match self {
+            TileFormat::Snes4 | TileFormat::Snes8 | TileFormat::Gba4 | TileFormat::Gba8 => Some(ColorFormat::Bgr555),
+            TileFormat::Sms => Some(ColorFormat::Sms),
+            TileFormat::GameGear => Some(ColorFormat::GameGear),
+            TileFormat::Genesis => Some(ColorFormat::Genesis),
+            TileFormat::Vera1 | TileFormat::Vera2 | TileFormat::Vera4 | TileFormat::Vera8 => Some(ColorFormat::Vera),
+            _ => None,
+        }
+    }
+
+    /// Decodes one tile into colour indices, row by row.
+    pub fn decode(self, bytes: &[u8]) -> [u8; 64] {
+        let mut tile = [0; 64];
+        match self.layout() {
+            Layout::Planar(planes) => {
+                for (bit, &(start, stride)) in planes.iter().enumerate() {
+                    for y in 0..8 {
+                        let row = bytes[start + y * stride];
+                        for x in 0..8 {
+                            tile[y * 8 + x] |= (row >> (7 - x) & 1) << bit;
+                        }
+                    }
+                }
+            }
+            Layout::Packed { bits, low_first, wide } => {
+                for (i, pixel) in tile.iter_mut().enumerate() {
+                    let (byte, shift) = packed_position(i, bits, low_first, wide);
+                    *pixel = bytes[byte] >> shift & ((1u16 << bits) - 1) as u8;
+                }
+            }
+            Layout::Interleaved(shifts) => {
+                for (i, pixel) in tile.iter_mut().enumerate() {
+                    let (byte, slot) = (i / (8 / shifts.len()), i % (8 / shifts.len()));
+                    for (bit, &shift) in shifts.iter().enumerate() {
+                        *pixel |= (bytes[byte] >> (shift - slot) & 1) << bit;
+                    }
+                }
+            }
+        }
+        tile
+    }
+
+    /// Encodes colour indices, row by row, as one tile.
+    pub fn encode(self, tile: &[u8; 64]) -> Vec<u8> {
+        let mut bytes = vec![0; self.tile_bytes()];
+        match self.layout() {
+            Layout::Planar(planes) => {
+                for (bit, &(start, stride)) in planes.iter().enumerate() {
+                    for y in 0..8 {
+                        for x in 0..8 {
+                            bytes[start + y * stride] |= (tile[y * 8 + x] >> bit & 1) << (7 - x);
+                        }
+                    }
+                }
+            }
+            Layout::Packed { bits, low_first, wide } => {
+                // Wide pixels are taken from the left one of each pair.
+                for (i, &pixel) in tile.iter().enumerate().filter(|(i, _)| !wide || i % 2 == 0) {
+                    let (byte, shift) = packed_position(i, bits, low_first, wide);
+                    bytes[byte] |= (pixel & ((1u16 << bits) - 1) as u8) << shift;
+                }
+            }
+            Layout::Interleaved(shifts) => {
+                for (i, &pixel) in tile.iter().enumerate() {
+                    let (byte, slot) = (i / (8 / shifts.len()), i % (8 / shifts.len()));
+                    for (bit, &shift) in shifts.iter().enumerate() {
+                        bytes[byte] |= (pixel >> bit & 1) << (shift - slot);
+                    }
+                }
+            }
+        }
+        bytes
+    }
+}
+
+/// How a format arranges the bits of a tile.
+enum Layout {
+    /// Bitplanes, see `TileFormat::planes`.
+    Planar(&'static [(usize, usize)]),
+    /// Whole pixels side by side in each byte, the first pixel in the low or the high bits.
+    /// Wide pixels each stand for two on screen.
+    Packed { bits: usize, low_first: bool, wide: bool },
+    /// Pixels side by side in each byte with their bits spread out, as on the CPC. Bit n of the
+    /// first pixel goes at the nth shift, and each following pixel one bit lower.
+    Interleaved(&'static [usize]),
+}
+
+/// The byte holding pixel `i` of a packed tile and how far up it is shifted.
+fn packed_position(i: usize, bits: usize, low_first: bool, wide: bool) -> (usize, usize) {
+    let i = if wide { i / 2 } else { i };
+    let per_byte = 8 / bits;
+    let slot = if low_first { i % per_byte } else { per_byte - 1 - i % per_byte };
+    (i / per_byte, slot * bits)
+}
+
+/// Ways palette entries are stored.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorFormat {
+    /// SNES CGRAM and GBA palette RAM: 0bbbbbgggggrrrrr, little-endian.
+    Bgr555,
+    /// Master System: one byte of 00bbggrr.
+    Sms,
+    /// Game Gear: 0000bbbbggggrrrr, little-endian.
+    GameGear,
+    /// Genesis CRAM: 0000bbb0ggg0rrr0, big-endian.
+    Genesis,
+    /// X16 VERA: 0000rrrrggggbbbb, little-endian.
+    Vera,
+}
+
+impl ColorFormat {
+    /// Packs a colour, dropping the bits the hardware does not have.
+    pub fn encode(self, color: [u8; 3]) -> u16 {
+        let [r, g, b] = color;
+        match self {
+            ColorFormat::Bgr555 => (b as u16 >> 3) << 10 | (g as u16 >> 3) << 5 | r as u16 >> 3,
+            ColorFormat::Sms => (b as u16 >> 6) << 4 | (g as u16 >> 6) << 2 | r as u16 >> 6,
+            ColorFormat::GameGear => (b as u16 >> 4) << 8 | (g as u16 >> 4) << 4 | r as u16 >> 4,
+            ColorFormat::Genesis => (b as u16 >> 5) << 9 | (g as u16 >> 5) << 5 | (r as u16 >> 5) << 1,
+            ColorFormat::Vera => (r as u16 >> 4) << 8 | (g as u16 >> 4) << 4 | b as u16 >> 4,
+        }
+    }
+
+    /// Bytes per palette entry.
+    pub fn size(self) -> usize {
+        match self {
+            ColorFormat::Sms => 1,
+            _ => 2,
+        }
+    }
+
+    /// The bytes of a palette as the hardware reads them.
+    pub fn encode_palette(self, palette: &[[u8; 3]]) -> Vec<u8> {
+        palette.iter().flat_map(|&x| match self {
+            ColorFormat::Genesis => self.encode(x).to_be_bytes().to_vec(),
+            _ => self.encode(x).to_le_bytes()[..self.size()].to_vec(),
+        }).collect()
+    }
+}
+
+/// The value paired with the colour closest to `color`, by squared RGB distance. Fixed hardware
+/// palettes use it to turn sheet colours into colour numbers or register values.
+pub fn nearest<T>(colors: impl IntoIterator<Item = (T, [u8; 3])>, color: [u8; 3]) -> T {
+    let distance = |x: &[u8; 3]| (0..3).map(|i| (x[i] as i32 - color[i] as i32).pow(2)).sum::<i32>();
+    colors.into_iter().min_by_key(|(_, x)| distance(x)).map(|(value, _)| value).unwrap()
+}
+
+/// Returns the hardware values a palette's colours came out as, or an error naming the first two that
+/// are the same, since the tiles would lose the difference between them. `describe` names a value.
+pub fn distinct<T: PartialEq>(values: Vec<T>, describe: impl Fn(&T) -> String) -> Result<Vec<T>, String> {
+    for (i, value) in values.iter().enumerate() {
+        if let Some(j) = values[..i].iter().position(|x| x == value) {
+            return Err(format!("Colors {} and {} are both {}.", j, i, describe(value)));
+        }
+    }
+    Ok(values)
+}
+
+/// Decodes every whole tile in `data`.
+pub fn decode_tiles(data: &[u8], format: TileFormat) -> Vec<[u8; 64]> {
+    data.chunks_exact(format.tile_bytes()).map(|x| format.decode(x)).collect()
+}
+
+/// Stretches a four colour preview palette to `colors` entries. Two colour tiles use its darkest
+/// and lightest colour, deeper ones a grey ramp.
+pub fn preview_palette(base: &[[u8; 3]; 4], colors: usize) -> Vec<[u8; 3]> {
+    match colors {
+        2 => vec![base[0], base[3]],
+        4 => base.to_vec(),
+        _ => (0..colors).map(|i| {
+            let level = (i * 255 / (colors - 1)) as u8;
+            [level, level, level]
+        }).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A tile whose rows step through the colours, row y holding colour (x + y) % colors at column x.
+    fn ramp(colors: u8) -> [u8; 64] {
+        let mut tile = [0; 64];
+        for (i, pixel) in tile.iter_mut().enumerate() {
+            *pixel = (i % 8 + i / 8) as u8 % colors;
+        }
+        tile
+    }
+
+    /// A tile whose first row holds `pixels` and the rest colour 0.
+    fn first_row(pixels: [u8; 8]) -> [u8; 64] {
+        let mut tile = [0; 64];
+        tile[..8].copy_from_slice(&pixels);
+        tile
+    }
+
+    #[test]
+    fn snes_4bpp_reference() {
+        // Planes 0 and 1 of a row sit together in the first 16 bytes, planes 2 and 3 in the next 16.
+        let bytes = TileFormat::Snes4.encode(&first_row([1, 2, 4, 8, 15, 0, 0, 0]));
+        let mut expected = [0; 32];
+        expected[..2].copy_from_slice(&[0x88, 0x48]);
+        expected[16..18].copy_from_slice(&[0x28, 0x18]);
+        assert_eq!(bytes, expected);
+        assert_eq!(TileFormat::Snes4.decode(&bytes), first_row([1, 2, 4, 8, 15, 0, 0, 0]));
+    }
+
+    #[test]
+    fn snes_8bpp_reference() {
+        // Each pair of planes takes 16 bytes, so plane n of row 0 is at 16 * (n / 2) + n % 2.
+        let mut tile = first_row([1, 2, 4, 8, 16, 32, 64, 128]);
+        tile[8..16].copy_from_slice(&[0xff; 8]);
+        let bytes = TileFormat::Snes8.encode(&tile);
+        let mut expected = [0; 64];
+        for plane in 0..8 {
+            expected[16 * (plane / 2) + plane % 2] = 0x80 >> plane;
+            expected[16 * (plane / 2) + plane % 2 + 2] = 0xff;
+        }
+        assert_eq!(bytes, expected);
+        assert_eq!(TileFormat::Snes8.decode(&bytes), tile);
+    }
+
+    #[test]
+    fn bgr555_reference() {
+        assert_eq!(ColorFormat::Bgr555.encode([0xff, 0x80, 0x20]), 0x121f);
+        assert_eq!(ColorFormat::Bgr555.encode_palette(&[[0xff, 0x80, 0x20], [0xff, 0xff, 0xff]]), [0x1f, 0x12, 0xff, 0x7f]);
+    }
+
+    #[test]
+    fn sms_reference() {
+        // The four plane bytes of each row are together, plane 0 first.
+        for format in [TileFormat::Sms, TileFormat::GameGear] {
+            let bytes = format.encode(&first_row([1, 2, 4, 8, 15, 0, 0, 0]));
+            let mut expected = [0; 32];
+            expected[..4].copy_from_slice(&[0x88, 0x48, 0x28, 0x18]);
+            assert_eq!(bytes, expected, "{}", format.name());
+        }
+    }
+
+    #[test]
+    fn sms_palette_reference() {
+        assert_eq!(ColorFormat::Sms.encode_palette(&[[0xff, 0x80, 0x20], [0xff, 0xff, 0xff]]), [0x0b, 0x3f]);
+        assert_eq!(ColorFormat::GameGear.encode_palette(&[[0xff, 0x80, 0x20], [0xff, 0xff, 0xff]]), [0x8f, 0x02, 0xff, 0x0f]);
+    }
+
+    #[test]
+    fn genesis_reference() {
+        // Leftmost pixel in the high nibble; palette words are big-endian.
+        let bytes = TileFormat::Genesis.encode(&first_row([1, 2, 3, 4, 5, 6, 7, 8]));
+        assert_eq!(bytes[..4], [0x12, 0x34, 0x56, 0x78]);
+        assert!(bytes[4..].iter().all(|&x| x == 0));
+        assert_eq!(ColorFormat::Genesis.encode([0xff, 0x80, 0x20]), 0x028e);
+        assert_eq!(ColorFormat::Genesis.encode_palette(&[[0xff, 0x80, 0x20]]), [0x02, 0x8e]);
+    }
+
+    #[test]
+    fn gba_reference() {
+        // 4bpp puts the leftmost pixel in the low nibble, the reverse of the Genesis.
+        let tile = first_row([1, 2, 3, 4, 5, 6, 7, 8]);
+        let bytes = TileFormat::Gba4.encode(&tile);
+        assert_eq!(bytes[..4], [0x21, 0x43, 0x65, 0x87]);
+        assert!(bytes[4..].iter().all(|&x| x == 0));
+        assert_eq!(TileFormat::Gba4.decode(&bytes), tile);
+
+        let bytes = TileFormat::Gba8.encode(&first_row([0x10, 0x20, 0x30, 0x40, 0x50, 0x60, 0x70, 0xff]));
+        assert_eq!(bytes.len(), 64);
+        assert_eq!(bytes[..8], [0x10, 0x20, 0x30, 0x40, 0x50, 0x60, 0x70, 0xff]);
+    }
+
+    #[test]
+    fn vera_reference() {
+        // Leftmost pixel in the high bits at every depth; palette words are little-endian.
+        let tile = first_row([1, 2, 3, 4, 5, 6, 7, 8]);
+        let bytes = TileFormat::Vera4.encode(&tile);
+        assert_eq!(bytes.len(), 32);
+        assert_eq!(bytes[..4], [0x12, 0x34, 0x56, 0x78]);
+        assert_eq!(TileFormat::Vera4.decode(&bytes), tile);
+
+        let bytes = TileFormat::Vera8.encode(&first_row([0x10, 0x20, 0x30, 0x40, 0x50, 0x60, 0x70, 0xff]));
+        assert_eq!(bytes.len(), 64);
+        assert_eq!(bytes[..8], [0x10, 0x20, 0x30, 0x40, 0x50, 0x60, 0x70, 0xff]);
+
+        assert_eq!(ColorFormat::Vera.encode([0xff, 0x80, 0x20]), 0x0f82);
+        assert_eq!(ColorFormat::Vera.encode_palette(&[[0xff, 0x80, 0x20]]), [0x82, 0x0f]);
+    }
+
+    #[test]
+    fn cpc_mode_1_reference() {
+        // Pen 1 sets the high nibble and pen 2 the low one, so a row of pens 0 to 3 twice is $53 $53.
+        let mut tile = [0; 64];
+        tile[..8].copy_from_slice(&[0, 1, 2, 3, 0, 1, 2, 3]);
+        tile[8..16].copy_from_slice(&[1; 8]);
+        tile[16..24].copy_from_slice(&[2; 8]);
+        tile[24..32].copy_from_slice(&[3; 8]);
+        let bytes = TileFormat::CpcMode1.encode(&tile);
+        assert_eq!(bytes.len(), 16);
+        assert_eq!(bytes[..8], [0x53, 0x53, 0xf0, 0xf0, 0x0f, 0x0f, 0xff, 0xff]);
+        assert_eq!(TileFormat::CpcMode1.decode(&bytes), tile);
+    }
+
+    #[test]
+    fn cpc_mode_0_reference() {
+        // Pen 1 in both pixels is $c0 and pen 15 is $ff; each pen's bits 0 to 3 go to bits 7, 3, 5 and 1.
+        let mut tile = [0; 64];
+        tile[..8].copy_from_slice(&[1, 1, 15, 15, 2, 0, 4, 8]);
+        let bytes = TileFormat::CpcMode0.encode(&tile);
+        assert_eq!(bytes.len(), 32);
+        assert_eq!(bytes[..4], [0xc0, 0xff, 0x08, 0x21]);
+        assert_eq!(TileFormat::CpcMode0.decode(&bytes), tile);
+    }
+
+    #[test]
+    fn atari_mode_4_reference() {
+        // Each byte holds four double-wide pixels, COLBK to COLPF2, leftmost in the high bits.
+        let mut tile = [0; 64];
+        tile[..8].copy_from_slice(&[0, 0, 1, 1, 2, 2, 3, 3]);
+        tile[8..16].copy_from_slice(&[3; 8]);
+        let bytes = TileFormat::AtariMode4.encode(&tile);
+        assert_eq!(bytes, [0x1b, 0xff, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(TileFormat::AtariMode4.decode(&bytes), tile);
+    }
+
+    #[test]
+    fn round_trips() {
+        for format in [TileFormat::CpcMode0, TileFormat::CpcMode1] {
+            let tile = ramp(format.colors() as u8);
+            assert_eq!(format.decode(&format.encode(&tile)), tile, "{}", format.name());
+        }
+    }
+}
@@ -0,0 +1,335 @@
+/// Ways exported tile data can be compressed, each with a decompressor that mirrors what a game would run.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    None,
+    /// PackBits: a byte n below $80 copies n + 1 bytes, one above repeats the next byte 257 - n times,
+    /// and $80 ends the data.
+    Rle,
+    /// An LZ4 block: literal runs and copies of up to 64K back, readable by any LZ4 block decoder.
+    Lz4,
+    /// Konami RLE: $01 to $80 repeats the next byte that many times, $81 to $fe copies n - $80 bytes
+    /// and $ff ends the data.
+    Konami,
+}
+
+impl Compression {
+    pub const ALL: [Compression; 4] = [Compression::None, Compression::Rle, Compression::Lz4, Compression::Konami];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::None => "None",
+            Compression::Rle => "RLE (PackBits)",
+            Compression::Lz4 => "LZ4 block",
+            Compression::Konami => "Konami RLE",
+        }
+    }
+
+    pub fn compress(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Compression::None => data.to_vec(),
+            Compression::Rle => rle(data),
+            Compression::Lz4 => lz4(data),
+            Compression::Konami => konami(data),
+        }
+    }
+
+    pub fn decompress(self, data: &[u8]) -> Result<Vec<u8>, String> {
+        match self {
+            Compression::None => Ok(data.to_vec()),
+            Compression::Rle => unrle(data),
+            Compression::Lz4 => unlz4(data),
+            Compression::Konami => unkonami(data),
+        }
+    }
+}
+
+/// Describes how much compression saved, such as `1234 of 4096 bytes (30%)`.
+pub fn report(compressed: usize, raw: usize) -> String {
+    format!("{} of {} bytes ({}%)", compressed, raw, (compressed * 100).checked_div(raw).unwrap_or(100))
+}
+
+/// How many times the byte at `i` repeats, up to `max`.
+fn run_length(data: &[u8], i: usize, max: usize) -> usize {
+    data[i..].iter().take(max).take_while(|&&x| x == data[i]).count()
+}
+
+fn rle(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut literals = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let run = run_length(data, i, 128);
+        if (run >= 2 || literals.len() == 128) && !literals.is_empty() {
+            out.push(literals.len() as u8 - 1);
+            out.append(&mut literals);
+        }
+        if run >= 2 {
+            out.extend([(257 - run) as u8, data[i]]);
+            i += run;
+        } else {
+            literals.push(data[i]);
+            i += 1;
+        }
+    }
+    if !literals.is_empty() {
+        out.push(literals.len() as u8 - 1);
+        out.append(&mut literals);
+    }
+    out.push(0x80);
+    out
+}
+
+fn unrle(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    loop {
+        let n = *data.get(i).ok_or("RLE data ends without $80.")?;
+        i += 1;
+        match n {
+            0x80 => return Ok(out),
+            0..=0x7f => {
+                let literals = data.get(i..i + n as usize + 1).ok_or("RLE data ends inside a literal run.")?;
+                out.extend_from_slice(literals);
+                i += n as usize + 1;
+            }
+            _ => {
+                let x = *data.get(i).ok_or("RLE data ends inside a repeat.")?;
+                out.resize(out.len() + 257 - n as usize, x);
+                i += 1;
+            }
+        }
+    }
+}
+
+fn konami(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut literals = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let run = run_length(data, i, 0x80);
+        if (run >= 2 || literals.len() == 0x7e) && !literals.is_empty() {
+            out.push(0x80 + literals.len() as u8);
+            out.append(&mut literals);
+        }
+        if run >= 2 {
+            out.extend([run as u8, data[i]]);
+            i += run;
+        } else {
+            literals.push(data[i]);
+            i += 1;
+        }
+    }
+    if !literals.is_empty() {
+        out.push(0x80 + literals.len() as u8);
+        out.append(&mut literals);
+    }
+    out.push(0xff);
+    out
+}
+
+fn unkonami(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    loop {
+        let n = *data.get(i).ok_or("Konami RLE data ends without $ff.")?;
+        i += 1;
+        match n {
+            0xff => return Ok(out),
+            0..=0x80 => {
+                let x = *data.get(i).ok_or("Konami RLE data ends inside a repeat.")?;
+                out.resize(out.len() + n as usize, x);
+                i += 1;
+            }
+            _ => {
+                let n = n as usize - 0x80;
+                out.extend_from_slice(data.get(i..i + n).ok_or("Konami RLE data ends inside a literal run.")?);
+                i += n;
+            }
+        }
+    }
+}
+
+/// Writes an LZ4 length past what its token holds: 255s and then the rest.
+fn lz4_length(out: &mut Vec<u8>, mut n: usize) {
+    while n >= 255 {
+        out.push(255);
+        n -= 255;
+    }
+    out.push(n as u8);
+}
+
+/// How many earlier positions sharing a hash `lz4` tries before settling for the best match so far.
+const LZ4_TRIES: usize = 256;
+
+/// Hashes the four bytes at the start of `data` into 16 bits.
+fn lz4_hash(data: &[u8]) -> usize {
+    (u32::from_le_bytes([data[0], data[1], data[2], data[3]]).wrapping_mul(2654435761) >> 16) as usize
+}
+
+fn lz4(data: &[u8]) -> Vec<u8> {
+    // LZ4 decoders expect the last 5 bytes as literals and no match starting in the last 12.
+    let n = data.len();
+    let mut out = Vec::new();
+    let mut anchor = 0;
+    let mut i = 0;
+
+    // Matches are found through hash chains: `head` holds the latest position whose first four bytes
+    // have each hash, and `chain` the position before each one with the same hash.
+    let mut head = vec![usize::MAX; 1 << 16];
+    let mut chain = vec![usize::MAX; n];
+    let mut hashed = 0;
+    while i + 12 < n {
+        for j in hashed..=i {
+            let hash = lz4_hash(&data[j..]);
+            chain[j] = head[hash];
+            head[hash] = j;
+        }
+        hashed = i + 1;
+
+        let max = n - 5 - i;
+        let (mut best, mut offset) = (0, 0);
+        let mut j = chain[i];
+        for _ in 0..LZ4_TRIES {
+            if j == usize::MAX || i - j > 0xffff {
+                break;
+            }
+            let len = (0..max).take_while(|&k| data[j + k] == data[i + k]).count();
+            if len > best {
+                best = len;
+                offset = i - j;
+                if len == max {
+                    break;
+                }
+            }
+            j = chain[j];
+        }
+
+        if best < 4 {
+            i += 1;
+            continue;
+        }
+        let literals = i - anchor;
+        out.push((literals.min(15) << 4 | (best - 4).min(15)) as u8);
+        if literals >= 15 {
+            lz4_length(&mut out, literals - 15);
+        }
+        out.extend_from_slice(&data[anchor..i]);
+        out.extend((offset as u16).to_le_bytes());
+        if best - 4 >= 15 {
+            lz4_length(&mut out, best - 4 - 15);
+        }
+        i += best;
+        anchor = i;
+    }
+
+    let literals = n - anchor;
+    out.push((literals.min(15) << 4) as u8);
+    if literals >= 15 {
+        lz4_length(&mut out, literals - 15);
+    }
+    out.extend_from_slice(&data[anchor..]);
+    out
+}
+
+fn read_byte(data: &[u8], i: &mut usize) -> Result<u8, String> {
+    let x = *data.get(*i).ok_or("LZ4 data ends inside a sequence.")?;
+    *i += 1;
+    Ok(x)
+}
+
+/// Reads the rest of an LZ4 length whose token holds `n`.
+fn read_length(data: &[u8], i: &mut usize, mut n: usize) -> Result<usize, String> {
+    if n == 15 {
+        loop {
+            let x = read_byte(data, i)?;
+            n += x as usize;
+            if x != 255 {
+                break;
+            }
+        }
+    }
+    Ok(n)
+}
+
+fn unlz4(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let token = read_byte(data, &mut i)?;
+        let literals = read_length(data, &mut i, (token >> 4) as usize)?;
+        out.extend_from_slice(data.get(i..i + literals).ok_or("LZ4 data ends inside literals.")?);
+        i += literals;
+        if i == data.len() {
+            break;
+        }
+
+        let offset = u16::from_le_bytes([read_byte(data, &mut i)?, read_byte(data, &mut i)?]) as usize;
+        let len = read_length(data, &mut i, (token & 15) as usize)? + 4;
+        if offset == 0 || offset > out.len() {
+            return Err(format!("LZ4 data copies from {} bytes back with only {} written.", offset, out.len()));
+        }
+        for _ in 0..len {
+            out.push(out[out.len() - offset]);
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reference tiles: blank, solid, a letter A, a checkerboard, a gradient and a pseudo-random tile,
+    /// repeated so the compressors find runs and matches.
+    fn reference_tiles() -> Vec<u8> {
+        let mut tiles = vec![0; 16];
+        tiles.extend([0xff; 16]);
+        tiles.extend([0x38, 0x6c, 0xc6, 0xc6, 0xfe, 0xc6, 0xc6, 0x00, 0x38, 0x6c, 0xc6, 0xc6, 0xfe, 0xc6, 0xc6, 0x00]);
+        tiles.extend([0xaa, 0x55, 0xaa, 0x55, 0xaa, 0x55, 0xaa, 0x55, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff]);
+        tiles.extend([0x0f, 0x0f, 0x0f, 0x0f, 0x0f, 0x0f, 0x0f, 0x0f, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x33]);
+        let mut seed = 12345u32;
+        for _ in 0..16 {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            tiles.push((seed >> 16) as u8);
+        }
+        let once = tiles.clone();
+        tiles.extend(once.iter().rev());
+        tiles.extend(once);
+        tiles
+    }
+
+    fn round_trip(compression: Compression, data: &[u8]) {
+        let compressed = compression.compress(data);
+        assert_eq!(compression.decompress(&compressed).unwrap(), data, "{}", compression.name());
+    }
+
+    #[test]
+    fn round_trips() {
+        let tiles = reference_tiles();
+        for compression in Compression::ALL {
+            round_trip(compression, &tiles);
+            round_trip(compression, &[]);
+            round_trip(compression, &[0; 4096]);
+            round_trip(compression, &tiles.iter().cycle().take(16 * 300).copied().collect::<Vec<u8>>());
+        }
+        for compression in [Compression::Rle, Compression::Lz4, Compression::Konami] {
+            round_trip(compression, &[1, 2, 3]);
+            round_trip(compression, &(0..1000).map(|x| (x * 7 % 251) as u8).collect::<Vec<u8>>());
+        }
+    }
+
+    #[test]
+    fn reference_encodings() {
+        assert_eq!(rle(&[1, 1, 1, 2, 3]), [0xfe, 1, 0x01, 2, 3, 0x80]);
+        assert_eq!(konami(&[1, 1, 1, 2, 3]), [3, 1, 0x82, 2, 3, 0xff]);
+        assert_eq!(lz4(&[7; 20]), [0x1a, 7, 1, 0, 0x50, 7, 7, 7, 7, 7]);
+    }
+
+    #[test]
+    fn compresses_repeats() {
+        let tiles = reference_tiles();
+        for compression in [Compression::Rle, Compression::Lz4, Compression::Konami] {
+            assert!(compression.compress(&tiles).len() < tiles.len(), "{}", compression.name());
+        }
+    }
+}
@@ -0,0 +1,312 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::chr::TileFormat;
+use crate::export::{self, Export, Table};
+use crate::sheet::Sheet;
+use crate::{atari, c64, cpc, tms, verify, zx};
+
+/// The highest tile number an export takes, so that gaps before it stay a sensible size.
+pub const MAX_TILE: usize = 0xffff;
+
+/// Converts the tiles a config assigns in a PNG sheet to `format`, laid out by tile number as in a
+/// CHR file. This is meant to be called from a build script, so it prints `cargo:rerun-if-changed`
+/// for both files:
+///
+/// ```no_run
+/// // In build.rs's main:
+/// let chr = lucifer_tile_editor::convert("gfx/font.png", "gfx/font.ltp", lucifer_tile_editor::chr::TileFormat::Nes).unwrap();
+/// std::fs::write(std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("font.chr"), chr).unwrap();
+/// ```
+///
+/// `config` is a file holding the tile config as Copy Config gives it, or a project whose `config=`
+/// line holds it. The tiles are encoded by `encode_tiles`, as Export Tiles and Copy ASM encode them,
+/// checking the format's colour limits, with the colours of the assigned tiles numbered by ascending red.
+pub fn convert<P: AsRef<Path>, Q: AsRef<Path>>(image: P, config: Q, format: TileFormat) -> Result<Vec<u8>, String> {
+    let (image, config) = (image.as_ref(), config.as_ref());
+    println!("cargo:rerun-if-changed={}", image.display());
+    println!("cargo:rerun-if-changed={}", config.display());
+    convert_files(image, config, format)
+}
+
+/// `convert` without the `cargo:rerun-if-changed` lines, for use outside build scripts.
+pub fn convert_files(image: &Path, config: &Path, format: TileFormat) -> Result<Vec<u8>, String> {
+    let sheet = Sheet::load_png(&image.to_string_lossy())?;
+    let text = fs::read_to_string(config).map_err(|e| format!("Could not read {}: {}", config.display(), e))?;
+    let text = text.lines().find_map(|x| x.strip_prefix("config=")).unwrap_or(&text);
+
+    let tiles = parse_config(text, &sheet)?.into_iter().map(|(k, r, c)| (k, (r, c))).collect();
+    let palette = verify::picture_palette(&sheet, &tiles);
+    Ok(encode_tiles(&sheet, &tiles, &palette, format)?.tile_data(format))
+}
+
+/// Like `convert`, but writes the tiles to `name` in `OUT_DIR` and returns the path written, ready for
+/// `include_bytes!(concat!(env!("OUT_DIR"), "/font.chr"))`.
+pub fn convert_to_out_dir<P: AsRef<Path>, Q: AsRef<Path>>(image: P, config: Q, format: TileFormat, name: &str) -> Result<PathBuf, String> {
+    let out_dir = std::env::var("OUT_DIR").map_err(|_| String::from("OUT_DIR is not set; call this from a build script."))?;
+    let path = Path::new(&out_dir).join(name);
+    let data = convert(image, config, format)?;
+    fs::write(&path, data).map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+/// Reads a tile config, `,0x05:1_2` entries giving each tile's row and column, checking every
+/// entry lies inside `sheet`.
+pub fn parse_config(config: &str, sheet: &Sheet) -> Result<Vec<(String, i32, i32)>, String> {
+    let mut parsed = Vec::new();
+    for tile in config.trim().split(',').filter(|x| x.len() > 3) {
+        let (key, pos) = tile.split_once(':').ok_or(format!("Malformed config entry \"{}\".", tile))?;
+        let (r, c) = pos.split_once('_').ok_or(format!("Malformed config entry \"{}\".", tile))?;
+        let r = r.trim().parse::<i32>().map_err(|_| format!("Bad row in config entry \"{}\".", tile))?;
+        let c = c.trim().parse::<i32>().map_err(|_| format!("Bad column in config entry \"{}\".", tile))?;
+
+        if r < 0 || c < 0 || r >= sheet.h() / 8 || c >= sheet.w() / 8 {
+            return Err(format!("Config entry \"{}\" is outside the picture.", tile));
+        }
+
+        parsed.push((String::from(key.trim()), r, c));
+    }
+    Ok(parsed)
+}
+
+/// Reads a tile number written as `0x05` or `$05`. Anything but hex digits after the prefix, such as a sign, is rejected.
+pub fn parse_index(key: &str) -> Option<usize> {
+    let key = key.trim();
+    let hex = key.strip_prefix("0x").or_else(|| key.strip_prefix('$'))?;
+    if hex.is_empty() || !hex.chars().all(|x| x.is_ascii_hexdigit()) {
+        return None;
+    }
+    usize::from_str_radix(hex, 16).ok()
+}
+
+/// Counts the distinct RGB colours of an RGBA tile, as `indices_in_palette` tells them apart.
+pub fn count_colors(tile: &[u8]) -> usize {
+    tile.chunks(4).map(|x| [x[0], x[1], x[2]]).collect::<BTreeSet<[u8; 3]>>().len()
+}
+
+/// Numbers the colours of an RGBA tile by looking each one up in `palette`.
+pub fn indices_in_palette(tile: &[u8], palette: &[[u8; 3]]) -> Result<[u8; 64], String> {
+    let mut result = [0; 64];
+    for (i, pixel) in tile.chunks(4).enumerate() {
+        let index = palette.iter().position(|x| x[..] == pixel[0..3]);
+        result[i] = index.ok_or(format!("Color #{:02x}{:02x}{:02x} is not in the palette.", pixel[0], pixel[1], pixel[2]))? as u8;
+    }
+    Ok(result)
+}
+
+/// Encodes the tiles at `(row, col)` in `sheet`, keyed by tile number, in `format`, with the palette or
+/// tables it needs. Formats with shared colours number every tile against `palette`, which is the
+/// palette of a decoded sheet or `verify::picture_palette` of a picture.
+pub fn encode_tiles(sheet: &Sheet, tiles: &BTreeMap<String, (i32, i32)>, palette: &[[u8; 3]], format: TileFormat) -> Result<Export, String> {
+    let mut pixels = BTreeMap::new();
+    for (k, &(r, c)) in tiles.iter() {
+        let i = parse_index(k).ok_or(format!("{} is not a tile number.", k))?;
+        if i > MAX_TILE {
+            return Err(format!("{} is past {:#06x}, the highest tile number an export takes.", k, MAX_TILE));
+        }
+        pixels.insert(i, sheet.tile(r, c).map_err(|e| format!("{}: {}", k, e))?);
+    }
+    let end = pixels.keys().last().map(|x| x + 1).unwrap_or(0);
+
+    match format {
+        TileFormat::C64Hires | TileFormat::C64Multi => {
+            let charset = c64::index(&pixels, format)?;
+            let mut colors = vec![0; end];
+            for (&i, &color) in charset.colors.iter() {
+                colors[i] = color;
+            }
+
+            let mut notes = vec![format!("Background ${:02x} goes in $d021.", charset.background)];
+            if let Some([first, second]) = charset.multicolor {
+                notes.push(format!("Multicolors ${:02x} and ${:02x} go in $d022 and $d023.", first, second));
+            }
+            notes.push(String::from("Colors holds the color RAM value of each character."));
+
+            Ok(Export {
+                tiles: charset.tiles.iter().map(|(&i, tile)| (i, format.encode(tile))).collect(),
+                palette: None,
+                tables: vec![Table { name: "Colors", bytes: colors, extension: "col" }],
+                notes,
+                ..Default::default()
+            })
+        }
+        TileFormat::Tms9918 => {
+            let mut tiles = BTreeMap::new();
+            let mut colors = vec![0; end * 8];
+            let mut bad = Vec::new();
+            for (&i, tile) in pixels.iter() {
+                match tms::encode(tile) {
+                    Ok((pattern, row_colors)) => {
+                        tiles.insert(i, pattern.to_vec());
+                        colors[i * 8..i * 8 + 8].copy_from_slice(&row_colors);
+                    }
+                    Err(_) => bad.push(format!("{:#04x}", i)),
+                }
+            }
+            if !bad.is_empty() {
+                return Err(format!("These tiles have rows of more than two colors: {}. Tools/Check/TMS9918 Rows marks them.", bad.join(", ")));
+            }
+
+            Ok(Export {
+                tiles,
+                palette: None,
+                tables: vec![Table { name: "Colors", bytes: colors, extension: "col" }],
+                notes: vec![String::from("Colors holds a foreground and background byte for each row of each pattern.")],
+                ..Default::default()
+            })
+        }
+        TileFormat::ZxSpectrum => {
+            let mut tiles = BTreeMap::new();
+            let mut attributes = vec![0; end];
+            let mut bad = Vec::new();
+            for (&i, tile) in pixels.iter() {
+                match zx::encode(tile) {
+                    Some((bitmap, attribute)) => {
+                        tiles.insert(i, bitmap.to_vec());
+                        attributes[i] = attribute;
+                    }
+                    None => bad.push(format!("{:#04x}", i)),
+                }
+            }
+            if !bad.is_empty() {
+                return Err(format!("These tiles clash: {}. Tools/Check/ZX Attribute Clashes marks them.", bad.join(", ")));
+            }
+
+            Ok(Export {
+                tiles,
+                palette: None,
+                tables: vec![Table { name: "Attributes", bytes: attributes, extension: "atr" }],
+                notes: vec![String::from("Attributes holds the ink, paper and bright bits of each tile.")],
+                ..Default::default()
+            })
+        }
+        TileFormat::CpcMode0 | TileFormat::CpcMode1 | TileFormat::AtariMode4 => {
+            if palette.len() > format.colors() {
+                return Err(format!("The sheet has {} colors, {} tiles allow {}.", palette.len(), format.name(), format.colors()));
+            }
+
+            let mut tiles = BTreeMap::new();
+            for (&i, tile) in pixels.iter() {
+                let indices = indices_in_palette(tile, palette).map_err(|e| format!("{:#04x}: {}", i, e))?;
+                if format.wide() && (0..32).any(|x| indices[x * 2] != indices[x * 2 + 1]) {
+                    return Err(format!("{:#04x} has pixels that are not in matching pairs.", i));
+                }
+                tiles.insert(i, format.encode(&indices));
+            }
+
+            let (table, note) = match format {
+                TileFormat::AtariMode4 => (
+                    Table { name: "Colors", bytes: atari::registers(palette)?, extension: "col" },
+                    "Colors holds COLOR4, then COLOR0 to COLOR2, for $02c8 and $02c4 to $02c6.",
+                ),
+                _ => (
+                    Table { name: "Inks", bytes: cpc::inks(palette)?, extension: "ink" },
+                    "Inks holds the firmware color of each ink, for INK or SCR SET INK.",
+                ),
+            };
+            Ok(Export {
+                tiles,
+                palette: None,
+                tables: vec![table],
+                notes: vec![String::from(note)],
+                ..Default::default()
+            })
+        }
+        _ => {
+            // Every tile is numbered against the one sheet palette so that they can share the exported palette.
+            if palette.len() > format.colors() {
+                return Err(format!("The sheet has {} colors, {} tiles allow {}.", palette.len(), format.name(), format.colors()));
+            }
+
+            let mut tiles = BTreeMap::new();
+            for (&i, tile) in pixels.iter() {
+                let indices = indices_in_palette(tile, palette).map_err(|e| format!("{:#04x}: {}", i, e))?;
+                tiles.insert(i, format.encode(&indices));
+            }
+            Ok(Export {
+                tiles,
+                palette: format.color_format().map(|x| (x, palette.to_vec())),
+                ..Default::default()
+            })
+        }
+    }
+}
+
+/// Writes the tiles at `(row, col)` in `sheet`, keyed by tile number, as `.org` blocks labelled `{prefix}0x05:`
+/// of `.db %xxxxxxxx` lines. The tiles are encoded by `encode_tiles` with `palette`, so the bytes are
+/// those Export Tiles and `convert` give.
+pub fn asm_patterns(sheet: &Sheet, tiles: &BTreeMap<String, (i32, i32)>, palette: &[[u8; 3]], format: TileFormat, prefix: &str) -> Result<String, String> {
+    let export = encode_tiles(sheet, tiles, palette, format)?;
+    let mut result = String::from("");
+    for k in tiles.keys() {
+        // `encode_tiles` has checked every key is a tile number.
+        let pattern = export::pattern_lines(&export.tiles[&parse_index(k).unwrap()], format.row_bytes());
+        result.push_str(&format!("\n;\n       .org {} * {}\n        {}{}:{}\n", k.replace("0x", "$"), format.tile_bytes(), prefix, k, pattern))
+    }
+    Ok(format!("{};", result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tile_numbers() {
+        assert_eq!(parse_index("0x1f"), Some(0x1f));
+        assert_eq!(parse_index(" $100 "), Some(0x100));
+        assert_eq!(parse_index("0x-1"), None);
+        assert_eq!(parse_index("0x+1"), None);
+        assert_eq!(parse_index("0x"), None);
+        assert_eq!(parse_index("12"), None);
+    }
+
+    #[test]
+    fn counts_rgb_colors() {
+        let tile = (0..64).flat_map(|i| if i % 2 == 0 { [0xff, 0, 0, 0xff] } else { [0xff, 0xff, 0xff, 0xff] }).collect::<Vec<u8>>();
+        assert_eq!(count_colors(&tile), 2);
+        assert_eq!(count_colors(&[0; 256]), 1);
+    }
+
+    #[test]
+    fn encodes_as_the_export_does() {
+        let palette = [[0, 0, 0], [0xd7, 0, 0], [0, 0xd7, 0], [0, 0, 0xd7], [0xff, 0xff, 0xff]];
+        let mut tile = [0; 64];
+        tile.iter_mut().enumerate().for_each(|(x, p)| *p = (x % 4) as u8);
+        let sheet = Sheet::from_tiles(&[tile, [4; 64]], 16, &palette);
+        let one = |k: &str, c: i32| std::iter::once((String::from(k), (0, c))).collect::<BTreeMap<String, (i32, i32)>>();
+
+        let export = encode_tiles(&sheet, &one("0x02", 0), &palette[..4], TileFormat::Nes).unwrap();
+        assert_eq!(export.tiles[&2], TileFormat::Nes.encode(&tile));
+        assert_eq!(export.tile_data(TileFormat::Nes).len(), 48);
+
+        assert!(encode_tiles(&sheet, &one("0x10000", 0), &palette[..4], TileFormat::Nes).unwrap_err().contains("highest tile number"));
+        assert!(encode_tiles(&sheet, &one("0x00", 0), &palette[..4], TileFormat::ZxSpectrum).unwrap_err().starts_with("These tiles clash"));
+        assert!(encode_tiles(&sheet, &one("0x00", 1), &palette[..4], TileFormat::Nes).unwrap_err().contains("not in the palette"));
+    }
+
+    #[test]
+    fn converts_as_asm_is_copied() {
+        // The second tile uses only the sheet's colours 2 and 3, which must keep their numbers.
+        let palette = [[0, 0, 0], [0x40, 0, 0], [0x80, 0, 0], [0xc0, 0, 0]];
+        let mut first = [0; 64];
+        first.iter_mut().enumerate().for_each(|(x, p)| *p = (x % 4) as u8);
+        let mut second = [2; 64];
+        second.iter_mut().skip(32).for_each(|p| *p = 3);
+        let sheet = Sheet::from_tiles(&[first, second], 16, &palette);
+
+        let dir = std::env::temp_dir();
+        let image = dir.join("lucifer-convert.png");
+        let config = dir.join("lucifer-convert.ltp");
+        sheet.save_png(&image.to_string_lossy()).unwrap();
+        fs::write(&config, "config=,0x00:0_0,0x01:0_1\n").unwrap();
+        let converted = convert_files(&image, &config, TileFormat::Nes).unwrap();
+        fs::remove_file(&image).unwrap();
+        fs::remove_file(&config).unwrap();
+
+        let tiles = parse_config(",0x00:0_0,0x01:0_1", &sheet).unwrap().into_iter().map(|(k, r, c)| (k, (r, c))).collect();
+        let text = asm_patterns(&sheet, &tiles, &verify::picture_palette(&sheet, &tiles), TileFormat::Nes, "Tile_").unwrap();
+        let copied = crate::asm::parse_patterns(&text, 16).unwrap().into_iter().flat_map(|x| x.bytes).collect::<Vec<u8>>();
+        assert_eq!(converted, copied);
+        assert_eq!(TileFormat::Nes.decode(&converted[16..]), second);
+    }
+}
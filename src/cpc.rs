@@ -0,0 +1,33 @@
+use crate::chr;
+
+/// The CPC firmware colour closest to an RGB colour. Firmware colour n has green, red and blue
+/// levels of n / 9, n / 3 % 3 and n % 3, each off, half or full.
+pub fn nearest(color: [u8; 3]) -> u8 {
+    let level = |x: u8| [0x00, 0x80, 0xff][x as usize];
+    chr::nearest((0..27).map(|n| (n, [level(n / 3 % 3), level(n / 9), level(n % 3)])), color)
+}
+
+/// The firmware colour of each ink, for `INK` or `SCR SET INK`, with ink 0 first.
+pub fn inks(palette: &[[u8; 3]]) -> Result<Vec<u8>, String> {
+    chr::distinct(palette.iter().map(|&x| nearest(x)).collect(), |x| format!("CPC color {}", x))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn firmware_colors() {
+        assert_eq!(nearest([0x00, 0x00, 0x00]), 0);
+        assert_eq!(nearest([0x00, 0x00, 0x80]), 1);
+        assert_eq!(nearest([0xff, 0x00, 0x00]), 6);
+        assert_eq!(nearest([0x00, 0xff, 0x00]), 18);
+        assert_eq!(nearest([0xff, 0xff, 0xff]), 26);
+    }
+
+    #[test]
+    fn inks_must_differ() {
+        assert_eq!(inks(&[[0, 0, 0], [0xff, 0xff, 0x00]]), Ok(vec![0, 24]));
+        assert!(inks(&[[0x00, 0x00, 0x00], [0x10, 0x10, 0x10]]).is_err());
+    }
+}
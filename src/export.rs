@@ -0,0 +1,464 @@
+use std::collections::BTreeMap;
+use crate::chr::{ColorFormat, TileFormat};
+use crate::compress::Compression;
+
+/// Assembler dialects tiles are written out for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Syntax {
+    /// `.org`, `Label:` and `.db %xxxxxxxx`, which asm6, ca65 and WLA-DX all read.
+    Db,
+    /// `dc.l $01230123` longwords for vasm and asm68k. Tiles follow one another, so gaps are filled.
+    Dc,
+    /// GNU as `.word 0x76543210` little-endian words, laid out like `Dc`.
+    Gas,
+    /// A C array of little-endian words, laid out like `Dc`.
+    C,
+    /// ACME `!byte $00`, a tile to a line, laid out like `Dc`.
+    Acme,
+    /// KickAssembler `.byte $00`, laid out like `Acme`.
+    Kick,
+    /// ca65 `.byte $00`, laid out like `Acme`.
+    Ca65,
+}
+
+impl Syntax {
+    pub const ALL: [Syntax; 7] = [Syntax::Db, Syntax::Dc, Syntax::Gas, Syntax::C, Syntax::Acme, Syntax::Kick, Syntax::Ca65];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Syntax::Db => "asm6, ca65 or WLA-DX .db",
+            Syntax::Dc => "vasm or asm68k dc.l",
+            Syntax::Gas => "GNU as .word",
+            Syntax::C => "C array",
+            Syntax::Acme => "ACME !byte",
+            Syntax::Kick => "KickAssembler .byte",
+            Syntax::Ca65 => "ca65 .byte",
+        }
+    }
+
+    /// The dialect usually used with the machine a format is for.
+    pub fn for_format(format: TileFormat) -> Syntax {
+        match format {
+            TileFormat::Genesis => Syntax::Dc,
+            TileFormat::Gba4 | TileFormat::Gba8 => Syntax::C,
+            TileFormat::C64Hires | TileFormat::C64Multi => Syntax::Acme,
+            TileFormat::Vera1 | TileFormat::Vera2 | TileFormat::Vera4 | TileFormat::Vera8 => Syntax::Ca65,
+            _ => Syntax::Db,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Syntax::C => "*.{c,h}",
+            _ => "*.{asm,s,inc,68k,a}",
+        }
+    }
+
+    fn comment(self) -> &'static str {
+        match self {
+            Syntax::Db | Syntax::Dc | Syntax::Acme | Syntax::Ca65 => ";",
+            Syntax::Gas => "@",
+            Syntax::C | Syntax::Kick => "//",
+        }
+    }
+
+    /// Writes a label on a line of its own.
+    fn label(self, name: &str) -> String {
+        match self {
+            Syntax::Db => format!("        {}:\n", name),
+            Syntax::Acme => format!("{}\n", name),
+            _ => format!("{}:\n", name),
+        }
+    }
+}
+
+/// What an export is saved as.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Output {
+    /// Assembly source in a dialect.
+    Asm(Syntax),
+    /// `const unsigned char` arrays, with a header of lengths and tile numbers.
+    C,
+    /// `pub static` byte arrays.
+    Rust,
+    /// The raw bytes, starting with the format's load address when `true`.
+    Binary(bool),
+}
+
+impl Output {
+    /// Every choice in the order the export dialog lists them.
+    pub fn all() -> Vec<Output> {
+        let mut all = Syntax::ALL.iter().map(|&x| Output::Asm(x)).collect::<Vec<Output>>();
+        all.extend([Output::C, Output::Rust, Output::Binary(false), Output::Binary(true)]);
+        all
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Output::Asm(syntax) => syntax.name(),
+            Output::C => "C source and header",
+            Output::Rust => "Rust source",
+            Output::Binary(false) => "Binary",
+            Output::Binary(true) => "Binary with load address",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Output::Asm(syntax) => syntax.extension(),
+            Output::C => "*.c",
+            Output::Rust => "*.rs",
+            Output::Binary(_) => "*.{bin,chr,64c,raw}",
+        }
+    }
+}
+
+/// Everything an export writes: the encoded tiles, keyed by tile number, and what goes with them.
+#[derive(Clone, Debug, Default)]
+pub struct Export {
+    pub tiles: BTreeMap<usize, Vec<u8>>,
+    /// The palette the tiles index, for formats whose palette is not fixed.
+    pub palette: Option<(ColorFormat, Vec<[u8; 3]>)>,
+    /// Further byte tables, such as colour attributes.
+    pub tables: Vec<Table>,
+    /// Lines for the header comment, such as register settings the data relies on.
+    pub notes: Vec<String>,
+    /// The tiles as a binary holds them, compressed, when they are written that way.
+    pub compressed: Option<(Compression, Vec<u8>)>,
+}
+
+impl Export {
+    /// Compresses the tiles as a binary lays them out, so that every output writes the compressed bytes instead.
+    pub fn compress(&mut self, compression: Compression, format: TileFormat) {
+        self.compressed = match compression {
+            Compression::None => None,
+            _ => Some((compression, compression.compress(&binary(&self.tiles, format.tile_bytes())))),
+        };
+    }
+
+    /// Fails when no tiles are assigned, as C rejects the empty arrays an export of nothing would hold.
+    fn check_tiles(&self) -> Result<(), String> {
+        match self.tiles.is_empty() {
+            true => Err(String::from("No tiles are assigned.")),
+            false => Ok(()),
+        }
+    }
+
+    /// The bytes of the tiles as a binary holds them.
+    pub fn tile_data(&self, format: TileFormat) -> Vec<u8> {
+        match &self.compressed {
+            Some((_, bytes)) => bytes.clone(),
+            None => binary(&self.tiles, format.tile_bytes()),
+        }
+    }
+}
+
+/// A byte table written after the tiles, labelled `{prefix}{name}`, or saved next to a binary export.
+#[derive(Clone, Debug)]
+pub struct Table {
+    pub name: &'static str,
+    pub bytes: Vec<u8>,
+    pub extension: &'static str,
+}
+
+/// Writes a whole export as source.
+pub fn text(export: &Export, format: TileFormat, syntax: Syntax, prefix: &str) -> Result<String, String> {
+    export.check_tiles()?;
+    let mut text = header_comment(export, format, syntax.comment());
+    match &export.compressed {
+        Some((_, bytes)) => text.push_str(&compressed_text(bytes, syntax, prefix)),
+        None => text.push_str(&tiles_text(&export.tiles, format, syntax, prefix)),
+    }
+
+    if let Some((color_format, palette)) = &export.palette {
+        let entries = palette.iter().map(|&x| color_format.encode(x)).collect::<Vec<u16>>();
+        text.push_str(&palette_text(&entries, *color_format, syntax, prefix));
+    }
+    for table in export.tables.iter() {
+        text.push_str(&table_text(table, syntax, prefix));
+    }
+    Ok(text)
+}
+
+/// Writes encoded tiles, keyed by tile number, as assembly labelled `{prefix}0x05:` and so on.
+fn tiles_text(tiles: &BTreeMap<usize, Vec<u8>>, format: TileFormat, syntax: Syntax, prefix: &str) -> String {
+    let mut text = String::new();
+    let end = tiles.keys().last().map(|x| x + 1).unwrap_or(0);
+    let gap = vec![0; format.tile_bytes()];
+
+    match syntax {
+        Syntax::Db => {
+            for (i, bytes) in tiles.iter() {
+                text.push_str(&format!("\n;\n       .org ${:02x} * {}\n        {}{:#04x}:{}\n", i, format.tile_bytes(), prefix, i, pattern_lines(bytes, format.row_bytes())));
+            }
+        }
+        Syntax::Dc => {
+            text.push_str(&format!("\n{}Tiles:\n", prefix));
+            for i in 0..end {
+                match tiles.get(&i) {
+                    Some(bytes) => text.push_str(&format!("{}{:#04x}:{}\n", prefix, i, long_lines(bytes))),
+                    None => text.push_str(&format!("        dcb.l   {},0\n", format.tile_bytes() / 4)),
+                }
+            }
+        }
+        Syntax::Gas => {
+            text.push_str(&format!("\n        .section .rodata\n        .align  2\n        .global {}Tiles\n{}Tiles:\n", prefix, prefix));
+            for i in 0..end {
+                match tiles.get(&i) {
+                    Some(bytes) => text.push_str(&format!("{}{:#04x}:{}\n", prefix, i, word_lines(bytes, format.row_bytes(), "        .word   "))),
+                    None => text.push_str(&format!("        .space  {}\n", format.tile_bytes())),
+                }
+            }
+        }
+        Syntax::C => {
+            text.push_str(&format!("\nconst unsigned int {}Tiles[{}] __attribute__((aligned(4))) = {{\n", prefix, end * format.tile_bytes() / 4));
+            for i in 0..end {
+                text.push_str(&format!("    // {:#04x}\n", i));
+                for line in word_lines(tiles.get(&i).unwrap_or(&gap), format.row_bytes(), "    ").lines().skip(1) {
+                    text.push_str(&format!("{},\n", line));
+                }
+            }
+            text.push_str("};\n");
+        }
+        Syntax::Acme | Syntax::Kick | Syntax::Ca65 => {
+            text.push('\n');
+            text.push_str(&syntax.label(&format!("{}Tiles", prefix)));
+            for i in 0..end {
+                for line in byte_lines(tiles.get(&i).unwrap_or(&gap), 8, syntax).lines().skip(1) {
+                    text.push_str(&format!("{}    {} {:#04x}\n", line, syntax.comment(), i));
+                }
+            }
+        }
+    }
+    text
+}
+
+/// Writes the comment lines an export starts with: the format, the notes and any compression.
+fn header_comment(export: &Export, format: TileFormat, comment: &str) -> String {
+    let mut text = format!("{} {} tiles\n", comment, format.name());
+    for note in export.notes.iter() {
+        text.push_str(&format!("{} {}\n", comment, note));
+    }
+    if let Some((compression, _)) = &export.compressed {
+        text.push_str(&format!("{} Tiles are compressed with {}.\n", comment, compression.name()));
+    }
+    text
+}
+
+/// Writes compressed tiles as one byte table labelled `{prefix}Tiles`.
+fn compressed_text(bytes: &[u8], syntax: Syntax, prefix: &str) -> String {
+    table_text(&Table { name: "Tiles", bytes: bytes.to_vec(), extension: "bin" }, syntax, prefix)
+}
+
+/// Writes a palette already packed by `color_format`, labelled `{prefix}Palette`.
+fn palette_text(entries: &[u16], color_format: ColorFormat, syntax: Syntax, prefix: &str) -> String {
+    if syntax == Syntax::C {
+        let (kind, digits) = if color_format.size() == 1 { ("char", 2) } else { ("short", 4) };
+        let values = entries.iter().map(|x| format!("0x{:0digits$x}", x, digits = digits)).collect::<Vec<String>>();
+        let lines = values.chunks(8).map(|x| format!("    {}", x.join(", "))).collect::<Vec<String>>();
+        return format!("\nconst unsigned {} {}Palette[{}] = {{\n{}\n}};\n", kind, prefix, entries.len(), lines.join(",\n"));
+    }
+
+    let lines = match color_format.size() {
+        1 => byte_lines(&entries.iter().map(|&x| x as u8).collect::<Vec<u8>>(), 8, syntax),
+        _ => {
+            let (word, hex) = match syntax {
+                Syntax::Db => ("        .dw     ", "$"),
+                Syntax::Dc => ("        dc.w    ", "$"),
+                Syntax::Gas => ("        .hword  ", "0x"),
+                Syntax::Acme => ("        !word   ", "$"),
+                Syntax::Kick | Syntax::Ca65 | Syntax::C => ("        .word   ", "$"),
+            };
+            entries.chunks(8).map(|x| format!("\n{}{}", word, x.iter().map(|x| format!("{}{:04x}", hex, x)).collect::<Vec<String>>().join(", "))).collect()
+        }
+    };
+
+    match syntax {
+        Syntax::Db => format!("\n;\n        {}Palette:{}\n", prefix, lines),
+        _ => format!("\n{}{}\n", syntax.label(&format!("{}Palette", prefix)), lines.trim_start_matches('\n')),
+    }
+}
+
+fn table_text(table: &Table, syntax: Syntax, prefix: &str) -> String {
+    let name = format!("{}{}", prefix, table.name);
+    if syntax == Syntax::C {
+        let values = table.bytes.iter().map(|x| format!("0x{:02x}", x)).collect::<Vec<String>>();
+        let lines = values.chunks(16).map(|x| format!("    {}", x.join(", "))).collect::<Vec<String>>();
+        return format!("\nconst unsigned char {}[{}] = {{\n{}\n}};\n", name, table.bytes.len(), lines.join(",\n"));
+    }
+
+    let lines = byte_lines(&table.bytes, 16, syntax);
+    match syntax {
+        Syntax::Db => format!("\n;\n        {}:{}\n", name, lines),
+        _ => format!("\n{}{}\n", syntax.label(&name), lines.trim_start_matches('\n')),
+    }
+}
+
+/// Turns a file name into a C or Rust identifier, in lower case. One that would not start with a
+/// letter gets a `tiles_` prefix, since C reserves names like `_FOO_H` that start with an underscore.
+pub fn identifier(name: &str) -> String {
+    let mut identifier = name.chars().map(|x| if x.is_ascii_alphanumeric() { x.to_ascii_lowercase() } else { '_' }).collect::<String>();
+    if !identifier.starts_with(|x: char| x.is_ascii_alphabetic()) {
+        identifier.insert_str(0, "tiles_");
+    }
+    identifier
+}
+
+/// The arrays of an export as (name, bytes), the tiles laid out as in a binary export.
+fn arrays(export: &Export, format: TileFormat) -> Vec<(&'static str, Vec<u8>)> {
+    let mut arrays = vec![("tiles", export.tile_data(format))];
+    if let Some((color_format, palette)) = &export.palette {
+        arrays.push(("palette", color_format.encode_palette(palette)));
+    }
+    for table in export.tables.iter() {
+        arrays.push((table.name, table.bytes.clone()));
+    }
+    arrays
+}
+
+/// Writes the bytes of an array body as `0x00,` lines. With `tile_bytes` each tile starts with a `// 0x05` comment.
+fn array_lines(bytes: &[u8], tile_bytes: Option<usize>) -> String {
+    let mut text = String::new();
+    for (i, chunk) in bytes.chunks(tile_bytes.unwrap_or(16)).enumerate() {
+        if tile_bytes.is_some() {
+            text.push_str(&format!("    // {:#04x}\n", i));
+        }
+        for line in chunk.chunks(16) {
+            text.push_str(&format!("    {},\n", line.iter().map(|x| format!("0x{:02x}", x)).collect::<Vec<String>>().join(", ")));
+        }
+    }
+    text
+}
+
+/// Writes an export as C, returning the source and its header. `name` is the identifier the arrays
+/// and defines start with, and `header` the file name the source includes.
+pub fn c_source(export: &Export, format: TileFormat, name: &str, header: &str) -> Result<(String, String), String> {
+    export.check_tiles()?;
+    let upper = name.to_uppercase();
+    let comment = header_comment(export, format, "//");
+    let mut source = format!("{}\n#include \"{}\"\n", comment, header);
+    let mut header = format!("{}\n#ifndef {}_H\n#define {}_H\n\n#define {}_TILE_BYTES {}\n", comment, upper, upper, upper, format.tile_bytes());
+    let mut declarations = String::new();
+    for (array, bytes) in arrays(export, format) {
+        let array = array.to_lowercase();
+        let length = format!("{}_{}_LENGTH", upper, array.to_uppercase());
+        let tile_bytes = if array == "tiles" && export.compressed.is_none() { Some(format.tile_bytes()) } else { None };
+        source.push_str(&format!("\nconst unsigned char {}_{}[{}] = {{\n{}}};\n", name, array, length, array_lines(&bytes, tile_bytes)));
+        header.push_str(&format!("#define {} {}\n", length, bytes.len()));
+        declarations.push_str(&format!("extern const unsigned char {}_{}[{}];\n", name, array, length));
+    }
+
+    header.push('\n');
+    for i in export.tiles.keys() {
+        header.push_str(&format!("#define {}_TILE_{:02X} {:#04x}\n", upper, i, i));
+    }
+    header.push_str(&format!("\n{}\n#endif\n", declarations));
+    Ok((source, header))
+}
+
+/// Writes an export as Rust statics named `{NAME}_TILES` and so on.
+pub fn rust_source(export: &Export, format: TileFormat, name: &str) -> Result<String, String> {
+    export.check_tiles()?;
+    let upper = name.to_uppercase();
+    let mut source = header_comment(export, format, "//");
+    for (array, bytes) in arrays(export, format) {
+        let tile_bytes = if array == "tiles" && export.compressed.is_none() { Some(format.tile_bytes()) } else { None };
+        source.push_str(&format!("\npub static {}_{}: [u8; {}] = [\n{}];\n", upper, array.to_uppercase(), bytes.len(), array_lines(&bytes, tile_bytes)));
+    }
+    Ok(source)
+}
+
+/// Writes bytes in hex, `per_line` to a line, with the byte directive of `syntax`.
+fn byte_lines(bytes: &[u8], per_line: usize, syntax: Syntax) -> String {
+    let (lead, hex) = match syntax {
+        Syntax::Db => ("        .db     ", "$"),
+        Syntax::Dc => ("        dc.b    ", "$"),
+        Syntax::Gas => ("        .byte   ", "0x"),
+        Syntax::Acme => ("        !byte   ", "$"),
+        Syntax::Kick | Syntax::Ca65 | Syntax::C => ("        .byte   ", "$"),
+    };
+    bytes.chunks(per_line).map(|x| {
+        let bytes = x.iter().map(|x| format!("{}{:02x}", hex, x)).collect::<Vec<String>>();
+        format!("\n{}{}", lead, bytes.join(","))
+    }).collect()
+}
+
+/// Writes bytes in binary as `.db` lines, `per_line` to a line, so each line shows a row of the tile.
+pub fn pattern_lines(bytes: &[u8], per_line: usize) -> String {
+    bytes.chunks(per_line).map(|x| {
+        let bytes = x.iter().map(|x| format!("%{:08b}", x)).collect::<Vec<String>>();
+        format!("\n        .db     {}", bytes.join(","))
+    }).collect()
+}
+
+/// Writes bytes as little-endian `0x76543210` words, `per_line` bytes to a line after `lead`.
+fn word_lines(bytes: &[u8], per_line: usize, lead: &str) -> String {
+    bytes.chunks(per_line.max(4)).map(|x| {
+        let words = x.chunks(4).map(|x| format!("0x{:08x}", u32::from_le_bytes([x[0], x[1], x[2], x[3]]))).collect::<Vec<String>>();
+        format!("\n{}{}", lead, words.join(", "))
+    }).collect()
+}
+
+/// Writes bytes as big-endian `dc.l` longwords, one to a line. For packed 4bpp tiles each is a row of pixels.
+fn long_lines(bytes: &[u8]) -> String {
+    bytes.chunks(4).map(|x| {
+        let hex = x.iter().map(|x| format!("{:02x}", x)).collect::<String>();
+        format!("\n        dc.l    ${}", hex)
+    }).collect()
+}
+
+/// Lays encoded tiles out at the offsets their numbers give, as in a CHR file, zero-filling any gaps.
+pub fn binary(tiles: &BTreeMap<usize, Vec<u8>>, tile_bytes: usize) -> Vec<u8> {
+    let size = tiles.keys().last().map(|x| (x + 1) * tile_bytes).unwrap_or(0);
+    let mut data = vec![0; size];
+    for (i, bytes) in tiles.iter() {
+        data[i * tile_bytes..(i + 1) * tile_bytes].copy_from_slice(bytes);
+    }
+    data
+}
+
+/// Where a binary with a load address goes, for machines whose loaders read one. The X16's `VLOAD`
+/// skips the two header bytes and loads to the VRAM address it is given, so the header is left at
+/// zero. `BVLOAD` takes files without a header, which a plain binary export is.
+pub fn load_address(format: TileFormat) -> Option<u16> {
+    match format {
+        TileFormat::C64Hires | TileFormat::C64Multi => Some(0x3000),
+        TileFormat::Vera1 | TileFormat::Vera2 | TileFormat::Vera4 | TileFormat::Vera8 => Some(0x0000),
+        _ => None,
+    }
+}
+
+/// The BASIC command that loads a binary with a load address, as `load_address` writes it.
+pub fn load_command(format: TileFormat, file: &str) -> Option<String> {
+    match format {
+        TileFormat::C64Hires | TileFormat::C64Multi => Some(format!("LOAD \"{}\",8,1", file)),
+        TileFormat::Vera1 | TileFormat::Vera2 | TileFormat::Vera4 | TileFormat::Vera8 => Some(format!("VLOAD \"{}\",8,0,$0000", file)),
+        _ => None,
+    }
+}
+
+/// Whether the palette and tables saved next to a binary start with the load address too. On the X16
+/// every file is loaded the same way, while the C64's colour tables are copied into place by the program.
+pub fn addresses_extras(format: TileFormat) -> bool {
+    matches!(format, TileFormat::Vera1 | TileFormat::Vera2 | TileFormat::Vera4 | TileFormat::Vera8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identifiers_start_with_a_letter() {
+        assert_eq!(identifier("Font-8x8"), "font_8x8");
+        assert_eq!(identifier("8x8"), "tiles_8x8");
+        assert_eq!(identifier("_font"), "tiles__font");
+    }
+
+    #[test]
+    fn rejects_empty_exports() {
+        let export = Export::default();
+        assert_eq!(text(&export, TileFormat::Gba4, Syntax::C, "Tile_").unwrap_err(), "No tiles are assigned.");
+        assert_eq!(c_source(&export, TileFormat::Nes, "font", "font.h").unwrap_err(), "No tiles are assigned.");
+        assert!(rust_source(&export, TileFormat::Nes, "font").is_err());
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::BTreeMap;
+use crate::chr;
+
+/// The PICO-8 palette.
+const PICO8_PALETTE: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00], [0x1d, 0x2b, 0x53], [0x7e, 0x25, 0x53], [0x00, 0x87, 0x51],
+    [0xab, 0x52, 0x36], [0x5f, 0x57, 0x4f], [0xc2, 0xc3, 0xc7], [0xff, 0xf1, 0xe8],
+    [0xff, 0x00, 0x4d], [0xff, 0xa3, 0x00], [0xff, 0xec, 0x27], [0x00, 0xe4, 0x36],
+    [0x29, 0xad, 0xff], [0x83, 0x76, 0x9c], [0xff, 0x77, 0xa8], [0xff, 0xcc, 0xaa],
+];
+
+/// TIC-80's default palette, Sweetie 16.
+const TIC80_PALETTE: [[u8; 3]; 16] = [
+    [0x1a, 0x1c, 0x2c], [0x5d, 0x27, 0x5d], [0xb1, 0x3e, 0x53], [0xef, 0x7d, 0x57],
+    [0xff, 0xcd, 0x75], [0xa7, 0xf0, 0x70], [0x38, 0xb7, 0x64], [0x25, 0x71, 0x79],
+    [0x29, 0x36, 0x6f], [0x3b, 0x5d, 0xc9], [0x41, 0xa6, 0xf6], [0x73, 0xef, 0xf7],
+    [0xf4, 0xf4, 0xf4], [0x94, 0xb0, 0xc2], [0x56, 0x6c, 0x86], [0x33, 0x3c, 0x57],
+];
+
+const SPRITES: usize = 256;
+
+/// Fantasy consoles whose text cartridges can hold the sheet.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Console {
+    Pico8,
+    Tic80,
+}
+
+impl Console {
+    pub fn name(self) -> &'static str {
+        match self {
+            Console::Pico8 => "PICO-8",
+            Console::Tic80 => "TIC-80",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Console::Pico8 => "*.p8",
+            Console::Tic80 => "*.lua",
+        }
+    }
+
+    /// Writes RGBA sprites, keyed by sprite number, into a cartridge. Sprites already in `cart`
+    /// that are not replaced are kept, as is everything else in it; without a cart a new one is made.
+    pub fn write(self, cart: Option<&str>, sprites: &BTreeMap<usize, Vec<u8>>) -> Result<String, String> {
+        if let Some(i) = sprites.keys().find(|&&i| i >= SPRITES) {
+            return Err(format!("{} has {} sprites, so {:#04x} does not fit.", self.name(), SPRITES, i));
+        }
+        match self {
+            Console::Pico8 => write_p8(cart, sprites),
+            Console::Tic80 => write_tic(cart, sprites),
+        }
+    }
+}
+
+fn nearest(palette: &[[u8; 3]; 16], pixel: &[u8]) -> u8 {
+    chr::nearest((0..16).zip(palette.iter().copied()), [pixel[0], pixel[1], pixel[2]])
+}
+
+fn hex_digit(x: u8) -> char {
+    std::char::from_digit(x as u32, 16).unwrap()
+}
+
+/// Replaces the `__gfx__` section of a `.p8` cart: 128 lines of 128 hex digits, one per pixel,
+/// with sprite n at column n % 16 and row n / 16.
+fn write_p8(cart: Option<&str>, sprites: &BTreeMap<usize, Vec<u8>>) -> Result<String, String> {
+    let cart = cart.unwrap_or("pico-8 cartridge // http://www.pico-8.com\nversion 41\n");
+    let mut lines = cart.lines().map(String::from).collect::<Vec<String>>();
+
+    let start = lines.iter().position(|x| x.trim() == "__gfx__");
+    let (start, end) = match start {
+        Some(start) => {
+            let end = lines[start + 1..].iter().position(|x| x.starts_with("__") && x.trim().ends_with("__")).map(|x| x + start + 1).unwrap_or(lines.len());
+            (start + 1, end)
+        }
+        None => {
+            lines.push(String::from("__gfx__"));
+            (lines.len(), lines.len())
+        }
+    };
+
+    let mut gfx = vec![vec!['0'; 128]; 128];
+    for (y, line) in lines[start..end].iter().take(128).enumerate() {
+        for (x, c) in line.chars().take(128).enumerate() {
+            gfx[y][x] = c;
+        }
+    }
+
+    for (&i, sprite) in sprites.iter() {
+        for (j, pixel) in sprite.chunks(4).enumerate() {
+            gfx[i / 16 * 8 + j / 8][i % 16 * 8 + j % 8] = hex_digit(nearest(&PICO8_PALETTE, pixel));
+        }
+    }
+
+    lines.splice(start..end, gfx.iter().map(|x| x.iter().collect::<String>()));
+    Ok(lines.join("\n") + "\n")
+}
+
+/// Replaces the `<TILES>` block of a TIC-80 Lua cart, where each tile is a `-- 000:` comment line of
+/// 64 hex digits, one per pixel. Colours are matched against the cart's `<PALETTE>` when it has one.
+fn write_tic(cart: Option<&str>, sprites: &BTreeMap<usize, Vec<u8>>) -> Result<String, String> {
+    let cart = cart.unwrap_or("-- title:  tiles\n-- script: lua\n\nfunction TIC()\nend\n");
+    let mut lines = cart.lines().map(String::from).collect::<Vec<String>>();
+
+    let palette = read_tic_palette(&lines).unwrap_or(TIC80_PALETTE);
+
+    let start = lines.iter().position(|x| x.trim() == "-- <TILES>");
+    let end = lines.iter().position(|x| x.trim() == "-- </TILES>");
+    let mut tiles = BTreeMap::new();
+    let range = match (start, end) {
+        (Some(start), Some(end)) if start < end => {
+            for line in lines[start + 1..end].iter() {
+                if let Some((i, data)) = line.trim_start_matches("-- ").split_once(':') {
+                    if let Ok(i) = i.parse::<usize>() {
+                        tiles.insert(i, String::from(data.trim()));
+                    }
+                }
+            }
+            start..end + 1
+        }
+        _ => {
+            lines.push(String::new());
+            lines.len()..lines.len()
+        }
+    };
+
+    for (&i, sprite) in sprites.iter() {
+        tiles.insert(i, sprite.chunks(4).map(|x| hex_digit(nearest(&palette, x))).collect());
+    }
+
+    let mut block = vec![String::from("-- <TILES>")];
+    block.extend(tiles.iter().map(|(i, data)| format!("-- {:03}:{}", i, data)));
+    block.push(String::from("-- </TILES>"));
+    lines.splice(range, block);
+    Ok(lines.join("\n") + "\n")
+}
+
+fn read_tic_palette(lines: &[String]) -> Option<[[u8; 3]; 16]> {
+    let start = lines.iter().position(|x| x.trim() == "-- <PALETTE>")?;
+    let hex = lines.get(start + 1)?.trim().trim_start_matches("-- ").split_once(':')?.1.trim().to_string();
+    let mut palette = [[0; 3]; 16];
+    for (i, color) in palette.iter_mut().enumerate() {
+        for (j, x) in color.iter_mut().enumerate() {
+            let k = (i * 3 + j) * 2;
+            *x = u8::from_str_radix(hex.get(k..k + 2)?, 16).ok()?;
+        }
+    }
+    Some(palette)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An RGBA sprite whose first row holds palette colours 0 to 7 and the rest colour 15.
+    fn sprite(palette: &[[u8; 3]; 16]) -> Vec<u8> {
+        (0..64).flat_map(|i| {
+            let [r, g, b] = palette[if i < 8 { i } else { 15 }];
+            vec![r, g, b, 0xff]
+        }).collect()
+    }
+
+    #[test]
+    fn p8_gfx() {
+        // Sprite 17 is the second on the second row of sprites, so it starts at line 8, column 8.
+        let cart = "pico-8 cartridge // http://www.pico-8.com\nversion 41\n__gfx__\n0123\n__map__\n00\n";
+        let sprites = std::iter::once((17, sprite(&PICO8_PALETTE))).collect();
+        let text = write_p8(Some(cart), &sprites).unwrap();
+        let lines = text.lines().collect::<Vec<&str>>();
+
+        assert_eq!(lines[3], format!("0123{}", "0".repeat(124)));
+        assert_eq!(&lines[3 + 8][..24], "000000000123456700000000");
+        assert_eq!(&lines[3 + 9][..24], "00000000ffffffff00000000");
+        assert_eq!(lines[3 + 128..], ["__map__", "00"]);
+    }
+
+    #[test]
+    fn tic_tiles() {
+        let cart = "-- script: lua\n-- <TILES>\n-- 000:11111111\n-- </TILES>\n";
+        let sprites = std::iter::once((2, sprite(&TIC80_PALETTE))).collect();
+        let text = write_tic(Some(cart), &sprites).unwrap();
+        let lines = text.lines().collect::<Vec<&str>>();
+
+        assert_eq!(lines[1..3], ["-- <TILES>", "-- 000:11111111"]);
+        assert_eq!(lines[3], format!("-- 002:01234567{}", "f".repeat(56)));
+        assert_eq!(lines[4], "-- </TILES>");
+        assert_eq!(lines.len(), 5);
+    }
+}
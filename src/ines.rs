@@ -0,0 +1,106 @@
+const HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
+const PRG_UNIT: usize = 16 * 1024;
+const CHR_UNIT: usize = 8 * 1024;
+
+/// A ROM image in the iNES or NES 2.0 format.
+#[derive(Clone, Debug)]
+pub struct Rom {
+    pub data: Vec<u8>,
+    pub nes2: bool,
+    pub chr_offset: usize,
+    pub chr_size: usize,
+}
+
+impl Rom {
+    pub fn parse(data: Vec<u8>) -> Result<Rom, String> {
+        if data.len() < HEADER_SIZE || &data[0..4] != b"NES\x1a" {
+            return Err(String::from("Not an iNES ROM."));
+        }
+
+        let nes2 = data[7] & 0x0c == 0x08;
+        let trainer = if data[6] & 0x04 != 0 { TRAINER_SIZE } else { 0 };
+
+        // NES 2.0 keeps the upper bits of both sizes in byte 9.
+        let (prg_msb, chr_msb) = if nes2 { (data[9] & 0x0f, data[9] >> 4) } else { (0, 0) };
+        let prg_size = rom_size(data[4], prg_msb, PRG_UNIT);
+        let chr_size = rom_size(data[5], chr_msb, CHR_UNIT);
+        let chr_offset = (HEADER_SIZE + trainer).saturating_add(prg_size);
+        let end = chr_offset.saturating_add(chr_size);
+
+        if end > data.len() {
+            return Err(format!("ROM is truncated: the header needs {} bytes but the file has {}.", end, data.len()));
+        }
+
+        Ok(Rom { data, nes2, chr_offset, chr_size })
+    }
+
+    pub fn chr(&self) -> &[u8] {
+        &self.data[self.chr_offset..self.chr_offset + self.chr_size]
+    }
+
+    pub fn chr_banks(&self) -> usize {
+        self.chr_size / CHR_UNIT
+    }
+
+    /// Copies the ROM with each `(tile number, bytes)` written over the CHR-ROM tile of that number.
+    /// Returns the new ROM and the numbers of the tiles whose bytes changed.
+    pub fn with_tiles(&self, tiles: &[(usize, Vec<u8>)]) -> Result<(Vec<u8>, Vec<usize>), String> {
+        let end = |i: usize, bytes: &[u8]| i.checked_add(1).and_then(|x| x.checked_mul(bytes.len()));
+        let outside = tiles.iter()
+            .filter(|(i, bytes)| end(*i, bytes).is_none_or(|x| x > self.chr_size))
+            .map(|(i, _)| format!("{:#04x}", i)).collect::<Vec<String>>();
+        if !outside.is_empty() {
+            return Err(format!("Tiles past the end of the {} byte CHR-ROM: {}.", self.chr_size, outside.join(", ")));
+        }
+
+        let mut data = self.data.clone();
+        let mut changed = Vec::new();
+        for (i, bytes) in tiles.iter() {
+            let offset = self.chr_offset + i * bytes.len();
+            if data[offset..offset + bytes.len()] != bytes[..] {
+                data[offset..offset + bytes.len()].copy_from_slice(bytes);
+                changed.push(*i);
+            }
+        }
+        Ok((data, changed))
+    }
+}
+
+/// Works out a PRG or CHR size from its header bytes, including NES 2.0's exponent-multiplier notation.
+fn rom_size(lsb: u8, msb: u8, unit: usize) -> usize {
+    if msb == 0x0f {
+        let exponent = (lsb >> 2) as u32;
+        let multiplier = (lsb & 0x03) as usize * 2 + 1;
+        2usize.saturating_pow(exponent).saturating_mul(multiplier)
+    } else {
+        ((msb as usize) << 8 | lsb as usize) * unit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A ROM with 16K of PRG and 8K of CHR, all zero.
+    fn rom() -> Rom {
+        let mut data = b"NES\x1a\x01\x01\x00\x00".to_vec();
+        data.resize(HEADER_SIZE + PRG_UNIT + CHR_UNIT, 0);
+        Rom::parse(data).unwrap()
+    }
+
+    #[test]
+    fn writes_tiles() {
+        let rom = rom();
+        let (data, changed) = rom.with_tiles(&[(1, vec![0xff; 16]), (2, vec![0; 16])]).unwrap();
+        assert_eq!(changed, [1]);
+        assert_eq!(data[rom.chr_offset + 16..rom.chr_offset + 32], [0xff; 16]);
+    }
+
+    #[test]
+    fn rejects_tiles_outside_chr() {
+        let rom = rom();
+        assert!(rom.with_tiles(&[(512, vec![0; 16])]).is_err());
+        assert!(rom.with_tiles(&[(usize::MAX, vec![0; 16])]).is_err());
+    }
+}
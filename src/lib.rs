@@ -0,0 +1,21 @@
+//! Tile sheet conversion for retro consoles and computers, shared by the Lucifer Tile Editor and by
+//! build scripts that regenerate pattern tables with `convert`.
+
+pub mod asm;
+pub mod atari;
+pub mod c64;
+pub mod charmap;
+pub mod chr;
+pub mod compress;
+pub mod convert;
+pub mod cpc;
+pub mod export;
+pub mod fantasy;
+pub mod ines;
+pub mod patch;
+pub mod sheet;
+pub mod tms;
+pub mod verify;
+pub mod zx;
+
+pub use convert::{convert, convert_to_out_dir};
@@ -1,15 +1,37 @@
-use fltk::{image::*, app::*, browser::*, button::*, enums::*, input::*, prelude::*, window::*, frame::*, dialog::*, group::*};
+use fltk::{image::*, app::*, button::*, draw, enums::*, input::*, menu::*, prelude::*, window::*, frame::*, dialog::*, group::*};
 use fltk_theme::{WidgetTheme, ThemeType};
-use crate::Message::{DisplayImage, UpdateTiles, CursorEdited};
-use std::iter::Map;
+use crate::Message::{DisplayImage, UpdateTiles, CursorEdited, Status, HoverStatus, Stats, RecentChanged, ViewChanged};
+use std::cell::RefCell;
 use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+use sheet::Sheet;
+use lucifer_tile_editor::ines::Rom;
+use chr::TileFormat;
+
+use lucifer_tile_editor::{asm, charmap, chr, compress, export, fantasy, patch, sheet, tms, verify, zx};
+use lucifer_tile_editor::convert::{self, asm_patterns, count_colors, parse_config, parse_index};
 
 const APP_TITLE: &str = "Lucifer Tile Editor";
 const COPYRIGHT: &str = "Copyright (C) 2021 Aurora Realms Entertainment";
 const WIN_WIDTH: i32 = 800;
 const WIN_HEIGHT: i32 = 560;
+const MAX_RECENT: usize = 8;
+const MAX_UNDO: usize = 100;
 
 fn main() {
+    let args = std::env::args().collect::<Vec<String>>();
+    if args.get(1).map(|x| x.as_str()) == Some("--verify") {
+        match args.get(2) {
+            Some(project) => std::process::exit(verify::run_cli(project, args.get(3).map(|x| x.as_str()), args.get(4).map(|x| x.as_str()))),
+            None => {
+                eprintln!("Usage: {} --verify <project.ltp> [<exported .asm, .chr or .nes> [<compression of a .chr>]]", args[0]);
+                std::process::exit(2);
+            }
+        }
+    }
+
     let app = App::default();
 
     let widget_theme = WidgetTheme::new(ThemeType::Classic);
@@ -19,7 +41,10 @@ fn main() {
 
     let mut win = create_main_window(sender.clone());
 
-    let mut model = Model { sender: sender.clone(), dark_mode: false, image: None, tiles: BTreeMap::new(), cursor: 0, prefix: format!("Tile_") };
+    let mut model = Model::new(sender.clone());
+
+    win(RecentChanged(model.recent.clone()));
+    win(ViewChanged(model.view));
 
     while app.wait() {
         match receiver.recv() {
@@ -32,8 +57,65 @@ fn main() {
                     Message::ClickExportConfig => {
                         model.export_config();
                     }
-                    Message::ClickExportASM => {
-                        model.export_asm();
+                    Message::ClickExportASM(format) => {
+                        if let Err(e) = model.export_asm(format) {
+                            alert_default(&e);
+                        }
+                    }
+                    Message::ClickExportCharmap => {
+                        let ca65 = match choice_default("Copy the charmap as", "Cancel", "ca65 .charmap", "Lookup table") {
+                            1 => true,
+                            2 => false,
+                            _ => continue,
+                        };
+                        match model.export_charmap(ca65) {
+                            Ok(n) => win(Status(format!("Copied a charmap of {} tiles.", n))),
+                            Err(e) => alert_default(&e),
+                        }
+                    }
+                    Message::ClickSetCharacters => {
+                        let targets = model.charmap_targets();
+                        if targets.is_empty() {
+                            alert_default("Select assigned tiles, or move the tile cursor to one, to give them characters.");
+                            continue;
+                        }
+                        let current = targets.iter().map(|k| model.charmap.get(k).cloned().unwrap_or_default()).collect::<String>();
+                        let prompt = match targets.len() {
+                            1 => format!("Characters for {}{:#04x}:", model.prefix, targets[0]),
+                            n => format!("One character for each of the {} selected tiles, in order:", n),
+                        };
+                        if let Some(text) = input_default(&prompt, &current) {
+                            match model.set_characters(&targets, &text) {
+                                Ok(()) => win(Status(format!("Mapped characters to {} tiles.", targets.len()))),
+                                Err(e) => alert_default(&e),
+                            }
+                        }
+                    }
+                    Message::ClickExportTiles => {
+                        if let Some((format, output, compression)) = export_dialog() {
+                            match model.export_tiles(format, output, compression) {
+                                Ok(Some(report)) => win(Status(report)),
+                                Ok(None) => {}
+                                Err(e) => alert_default(&e),
+                            }
+                        }
+                    }
+                    Message::ClickExportCart(console) => {
+                        match model.export_cart(console) {
+                            Ok(Some(report)) => win(Status(report)),
+                            Ok(None) => {}
+                            Err(e) => alert_default(&e),
+                        }
+                    }
+                    Message::ClickExportRom => {
+                        match model.export_rom() {
+                            Ok(Some(report)) => {
+                                message_default(&report);
+                                win(Status(report));
+                            }
+                            Ok(None) => {}
+                            Err(e) => alert_default(&e),
+                        }
                     }
                     Message::ChangeTheme => {
                         if !model.dark_mode {
@@ -47,31 +129,279 @@ fn main() {
                         }
                     }
                     Message::ClickTile(r, c) => {
-                        if !event_key_down(Key::ControlL) {
+                        model.tile_cursor = Some((r, c));
+                        model.hex_entry.clear();
+                        if event_key_down(Key::ShiftL) || event_key_down(Key::ShiftR) {
+                            if !model.selection.remove(&(r, c)) {
+                                model.selection.insert((r, c));
+                            }
+                        } else if !event_key_down(Key::ControlL) {
+                            model.checkpoint();
                             model.set_tile(format!("{:#04x}", model.cursor), r, c);
                             model.cursor += 1;
                         } else {
+                            model.checkpoint();
+                            model.clear_tile(r, c);
+                        }
+                        win(UpdateTiles(model.clone()));
+                        win(CursorEdited(model.cursor));
+                    }
+                    Message::MoveTileCursor(dr, dc) => {
+                        model.hex_entry.clear();
+                        model.move_tile_cursor(dr, dc);
+                        win(UpdateTiles(model.clone()));
+                    }
+                    Message::AssignAtTileCursor => {
+                        model.hex_entry.clear();
+                        if let Some((r, c)) = model.tile_cursor {
+                            model.checkpoint();
+                            model.set_tile(format!("{:#04x}", model.cursor), r, c);
+                            model.cursor += 1;
+                            model.advance_tile_cursor();
+                        }
+                        win(UpdateTiles(model.clone()));
+                        win(CursorEdited(model.cursor));
+                    }
+                    Message::ClearAtTileCursor => {
+                        model.hex_entry.clear();
+                        if let Some((r, c)) = model.tile_cursor {
+                            model.checkpoint();
                             model.clear_tile(r, c);
                         }
                         win(UpdateTiles(model.clone()));
+                    }
+                    Message::StepCursor(delta) => {
+                        model.hex_entry.clear();
+                        model.cursor = (model.cursor + delta).max(0);
+                        win(CursorEdited(model.cursor));
+                    }
+                    Message::TypeHex(digit) => {
+                        // Only the last few digits count, so a typo is fixed by typing on.
+                        model.hex_entry.push(digit);
+                        if model.hex_entry.len() > 3 {
+                            model.hex_entry.remove(0);
+                        }
+                        model.cursor = i32::from_str_radix(&model.hex_entry, 16).unwrap_or(0);
+                        win(Status(format!("Index: {}", model.hex_entry)));
                         win(CursorEdited(model.cursor));
                     }
                     Message::ClickLoadConfig => {
-                        model.import_config(input_default("Config", "").unwrap_or(String::new()));
+                        if let Some(cfg) = input_default("Config", "") {
+                            model.checkpoint();
+                            if let Err(e) = model.import_config(cfg) {
+                                alert_default(&e);
+                            }
+                        }
                         win(UpdateTiles(model.clone()));
                     }
                     Message::ClickOpenImage => {
-                        model.load_png();
-
-                        let image = model.clone().image;
+                        if !discard_ok(&model) {
+                            continue;
+                        }
+                        if let Err(e) = model.load_png() {
+                            alert_default(&e);
+                        }
 
-                        if image.is_some() {
-                            win(DisplayImage(image.unwrap()))
+                        if let Some(image) = model.image.clone() {
+                            win(DisplayImage(image));
+                            win(UpdateTiles(model.clone()));
+                        }
+                    }
+                    Message::ClickImportRom => {
+                        if let Some(path) = file_chooser("Choose a ROM", "*.nes", "", false) {
+                            open_and_display(&mut model, &mut win, path);
+                        }
+                    }
+                    Message::ClickImportAsm => {
+                        if let Some(path) = file_chooser("Choose an assembly file", "*.{asm,s,inc}", "", false) {
+                            open_and_display(&mut model, &mut win, path);
+                        }
+                    }
+                    Message::ClickOpenChr => {
+                        if !discard_ok(&model) {
+                            continue;
+                        }
+                        if let Some(path) = file_chooser("Choose a CHR file", "*.{chr,bin}", "", false) {
+                            let len = fs::metadata(&path).map(|x| x.len() as usize).unwrap_or(0);
+                            if let Some((offset, length, format, palette)) = chr_import_dialog(len) {
+                                if let Err(e) = model.open_chr(path, offset, length, format, palette) {
+                                    alert_default(&e);
+                                    continue;
+                                }
+                                if !model.view.sheet {
+                                    model.view.sheet = true;
+                                    win(ViewChanged(model.view));
+                                }
+                                redisplay(&model, &mut win);
+                                win(CursorEdited(model.cursor));
+                            }
+                        }
+                    }
+                    Message::ClickSaveSheet => {
+                        match model.save_sheet() {
+                            Ok(Some(report)) => {
+                                win(Status(report));
+                                win(RecentChanged(model.recent.clone()));
+                            }
+                            Ok(None) => {}
+                            Err(e) => alert_default(&e),
+                        }
+                    }
+                    Message::ClickOpenProject => {
+                        if let Some(path) = file_chooser("Choose a project", "*.ltp", "", false) {
+                            open_and_display(&mut model, &mut win, path);
+                        }
+                    }
+                    Message::ClickRecent(path) => {
+                        open_and_display(&mut model, &mut win, path);
+                    }
+                    Message::ClickSaveProject(save_as) => {
+                        match model.save_project(save_as) {
+                            Ok(()) => win(RecentChanged(model.recent.clone())),
+                            Err(e) => alert_default(&e),
+                        }
+                    }
+                    Message::ClickQuit => {
+                        if !model.dirty || choice_default("Discard unsaved changes?", "Cancel", "Quit", "") == 1 {
+                            quit();
+                        }
+                    }
+                    Message::ClickUndo => {
+                        model.undo();
+                        win(UpdateTiles(model.clone()));
+                    }
+                    Message::ClickRedo => {
+                        model.redo();
+                        win(UpdateTiles(model.clone()));
+                    }
+                    Message::ClickSelectAll => {
+                        model.select_all();
+                        win(UpdateTiles(model.clone()));
+                    }
+                    Message::ClickSelectNone => {
+                        model.selection.clear();
+                        win(UpdateTiles(model.clone()));
+                    }
+                    Message::ClickClear => {
+                        // With nothing selected this empties the project, so it is never done on a stray keypress.
+                        if model.selection.is_empty() {
+                            let prompt = format!("Nothing is selected. Clear all {} assigned tiles?", model.tiles.len());
+                            if model.tiles.is_empty() || choice_default(&prompt, "Cancel", "Clear All", "") != 1 {
+                                continue;
+                            }
+                        }
+                        model.checkpoint();
+                        model.clear_selected();
+                        win(UpdateTiles(model.clone()));
+                    }
+                    Message::ClickAutoAssign => {
+                        model.checkpoint();
+                        let n = model.auto_assign();
+                        win(Status(format!("Assigned {} tiles.", n)));
+                        win(UpdateTiles(model.clone()));
+                        win(CursorEdited(model.cursor));
+                    }
+                    Message::ClickVerifyExport => {
+                        let path = match choice_default("Verify which export?", "Cancel", "ASM", "File...") {
+                            1 => None,
+                            2 => match file_chooser("Choose the exported file", "*.{asm,s,inc,chr,bin,nes}", "", false) {
+                                Some(path) => Some(path),
+                                None => continue,
+                            },
+                            _ => continue,
+                        };
+                        // Source files name their compression in the header, and ROMs are never compressed.
+                        let binary = path.as_ref().map(|x| x.to_lowercase()).filter(|x| ![".asm", ".s", ".inc", ".nes"].iter().any(|e| x.ends_with(e)));
+                        let compression = match binary {
+                            Some(_) => match compression_dialog() {
+                                Some(compression) => compression,
+                                None => continue,
+                            },
+                            None => compress::Compression::None,
+                        };
+                        match model.verify_export(path.as_deref(), compression) {
+                            Ok((mismatches, checked)) => {
+                                let report = verify::report(&mismatches, checked);
+                                win(Status(report.clone()));
+                                if mismatches.is_empty() {
+                                    message_default(&report);
+                                } else {
+                                    show_mismatches(&report, &mismatches);
+                                }
+                            }
+                            Err(e) => alert_default(&e),
+                        }
+                    }
+                    Message::CheckTiles(format) => {
+                        let n = model.check_tiles(format);
+                        win(Status(match n {
+                            0 => format!("Every tile fits {}.", format.name()),
+                            n => format!("{} tiles break the rules of {}; the rows are marked.", n, format.name()),
+                        }));
+                        win(UpdateTiles(model.clone()));
+                    }
+                    Message::ClearMarks => {
+                        model.marks.clear();
+                        win(UpdateTiles(model.clone()));
+                    }
+                    Message::ClickDedupe => {
+                        model.checkpoint();
+                        let n = model.dedupe();
+                        win(Status(format!("Removed {} duplicate tiles.", n)));
+                        win(UpdateTiles(model.clone()));
+                    }
+                    Message::ChangeZoom(delta) => {
+                        // A delta of zero resets to the default zoom.
+                        model.view.zoom = if delta == 0 { ViewOptions::default().zoom } else { (model.view.zoom + delta).clamp(1, 8) };
+                        win(ViewChanged(model.view));
+                    }
+                    Message::ToggleSheet => {
+                        model.view.sheet = !model.view.sheet;
+                        win(ViewChanged(model.view));
+                    }
+                    Message::ToggleTileGrid => {
+                        model.view.tile_grid = !model.view.tile_grid;
+                        win(ViewChanged(model.view));
+                    }
+                    Message::TogglePixelGrid => {
+                        model.view.pixel_grid = !model.view.pixel_grid;
+                        win(ViewChanged(model.view));
+                    }
+                    Message::ChooseTileGridColor => {
+                        let (r, g, b) = model.view.tile_grid_color.to_rgb();
+                        let (r, g, b) = color_chooser_with_default("Tile grid color", ColorMode::Byte, (r, g, b));
+                        model.view.tile_grid_color = Color::from_rgb(r, g, b);
+                        win(ViewChanged(model.view));
+                    }
+                    Message::ChoosePixelGridColor => {
+                        let (r, g, b) = model.view.pixel_grid_color.to_rgb();
+                        let (r, g, b) = color_chooser_with_default("Pixel grid color", ColorMode::Byte, (r, g, b));
+                        model.view.pixel_grid_color = Color::from_rgb(r, g, b);
+                        win(ViewChanged(model.view));
+                    }
+                    Message::ImageLoaded => {
+                        match (&model.image, &model.rom) {
+                            (Some(_), Some((_, rom))) => {
+                                let format = if rom.nes2 { "NES 2.0" } else { "iNES" };
+                                win(Status(format!("Imported {} tiles from {} CHR bank(s) of an {} ROM.", model.tiles.len(), rom.chr_banks(), format)));
+                            }
+                            (Some(image), None) => {
+                                win(Status(format!("Loaded {}x{} picture, {} tiles.", image.w(), image.h(), (image.w() / 8) * (image.h() / 8))));
+                            }
+                            _ => {}
                         }
                     }
+                    Message::HoverTile(r, c) => {
+                        model.hover = Some((r, c));
+                    }
 
                     _ => println!("{:?}", e),
                 }
+
+                if let Some((r, c)) = model.hover {
+                    win(HoverStatus(model.describe_tile(r, c)));
+                }
+                win(Stats(model.stats()));
             }
             None => {
                 win(Message::Nothing)
@@ -80,6 +410,56 @@ fn main() {
     }
 }
 
+/// Whether the model can be replaced: it has no unsaved changes, or the user agrees to lose them.
+fn discard_ok(model: &Model) -> bool {
+    !model.dirty || choice_default("Discard unsaved changes?", "Cancel", "Discard", "") == 1
+}
+
+/// Opens a project, or a picture or ROM depending on the extension of `path`, and shows it.
+fn open_and_display(model: &mut Model, win: &mut Box<dyn FnMut(Message)>, path: String) {
+    if !discard_ok(model) {
+        return;
+    }
+    let lower = path.to_lowercase();
+    let result = if lower.ends_with(".png") {
+        model.open_image(path)
+    } else if lower.ends_with(".nes") {
+        match choose_preview_palette() {
+            Some(palette) => model.open_rom(path, chr::PREVIEW_PALETTES[palette].1.to_vec()),
+            None => return,
+        }
+    } else if [".asm", ".s", ".inc"].iter().any(|x| lower.ends_with(x)) {
+        match choose_preview_palette() {
+            Some(palette) => model.open_asm(path, chr::PREVIEW_PALETTES[palette].1.to_vec()),
+            None => return,
+        }
+    } else {
+        model.open_project(path)
+    };
+
+    match result {
+        // ROM and assembled graphics are one contiguous sheet, so show them that way.
+        Ok(()) if model.image_path.is_none() && !model.view.sheet => {
+            model.view.sheet = true;
+            win(ViewChanged(model.view));
+        }
+        Ok(()) => {}
+        Err(e) => alert_default(&e),
+    }
+
+    win(RecentChanged(model.recent.clone()));
+    redisplay(model, win);
+    win(CursorEdited(model.cursor));
+}
+
+/// Shows the current picture and its tiles in the tile view.
+fn redisplay(model: &Model, win: &mut Box<dyn FnMut(Message)>) {
+    if let Some(image) = model.image.clone() {
+        win(DisplayImage(image));
+        win(UpdateTiles(model.clone()));
+    }
+}
+
 fn create_main_window(sender: Sender<Message>) -> Box<dyn FnMut(Message)> {
     let mut win = Window::default().with_size(WIN_WIDTH, WIN_HEIGHT).with_label(APP_TITLE);
     let mut flex = Flex::default().size_of_parent().column();
@@ -91,8 +471,8 @@ fn create_main_window(sender: Sender<Message>) -> Box<dyn FnMut(Message)> {
     let (mut bottom_pane_handler, mut bottom_pane) = create_bottom_pane(sender.clone());
     let (mut footer_pane_handler, mut footer_pane) = create_footer_pane(sender.clone());
 
-    flex.set_size(&mut footer_pane, 40);
-    flex.set_size(&mut top_pane, 25);
+    flex.set_size(&mut footer_pane, 60);
+    flex.set_size(&mut top_pane, 30);
     flex.set_size(&mut bottom_pane, 50);
 
     flex.end();
@@ -108,73 +488,246 @@ fn create_main_window(sender: Sender<Message>) -> Box<dyn FnMut(Message)> {
     })
 }
 
-fn create_top_pane(sender: Sender<Message>) -> (Box<dyn FnMut(Message)>, Flex) {
-    let flex = Flex::default().row();
+fn create_top_pane(sender: Sender<Message>) -> (Box<dyn FnMut(Message)>, MenuBar) {
+    let mut menu = MenuBar::default();
+
+    let ctrl = Shortcut::Ctrl;
+    let ctrl_shift = Shortcut::Ctrl | Shortcut::Shift;
+    let items = [
+        ("File/Open Image...", ctrl | 'o', Message::ClickOpenImage),
+        ("File/Open Project...", ctrl_shift | 'o', Message::ClickOpenProject),
+        ("File/Import ROM...", ctrl | 'i', Message::ClickImportRom),
+        ("File/Open CHR...", ctrl_shift | 'i', Message::ClickOpenChr),
+        ("File/Import ASM...", Shortcut::None, Message::ClickImportAsm),
+        ("File/Save Project", ctrl | 's', Message::ClickSaveProject(false)),
+        ("File/Save Project As...", ctrl_shift | 's', Message::ClickSaveProject(true)),
+        ("File/Load Config...", ctrl | 'l', Message::ClickLoadConfig),
+        ("File/Export/Copy ASM", ctrl | 'e', Message::ClickExportASM(TileFormat::Nes)),
+        ("File/Export/Copy 1bpp ASM", Shortcut::None, Message::ClickExportASM(TileFormat::Mono)),
+        ("File/Export/Copy Charmap...", Shortcut::None, Message::ClickExportCharmap),
+        ("File/Export/Copy Config", ctrl_shift | 'e', Message::ClickExportConfig),
+        ("File/Export/Export Tiles...", ctrl | Shortcut::Alt | 'e', Message::ClickExportTiles),
+        ("File/Export/Write to ROM...", Shortcut::None, Message::ClickExportRom),
+        ("File/Export/Write to PICO-8 Cart...", Shortcut::None, Message::ClickExportCart(fantasy::Console::Pico8)),
+        ("File/Export/Write to TIC-80 Cart...", Shortcut::None, Message::ClickExportCart(fantasy::Console::Tic80)),
+        ("File/Export/Save Sheet as PNG...", Shortcut::None, Message::ClickSaveSheet),
+        ("File/Quit", ctrl | 'q', Message::ClickQuit),
+        ("Edit/Undo", ctrl | 'z', Message::ClickUndo),
+        ("Edit/Redo", ctrl | 'y', Message::ClickRedo),
+        ("Edit/Select All", ctrl | 'a', Message::ClickSelectAll),
+        ("Edit/Select None", ctrl_shift | 'a', Message::ClickSelectNone),
+        ("Edit/Clear", Shortcut::None | Key::BackSpace, Message::ClickClear),
+        ("View/Zoom In", ctrl | '=', Message::ChangeZoom(1)),
+        ("View/Zoom Out", ctrl | '-', Message::ChangeZoom(-1)),
+        ("View/Reset Zoom", ctrl | '0', Message::ChangeZoom(0)),
+        ("View/Show as Sheet", ctrl_shift | 'v', Message::ToggleSheet),
+        ("View/Tile Grid", ctrl | 'g', Message::ToggleTileGrid),
+        ("View/Pixel Grid", ctrl_shift | 'g', Message::TogglePixelGrid),
+        ("View/Tile Grid Color...", Shortcut::None, Message::ChooseTileGridColor),
+        ("View/Pixel Grid Color...", Shortcut::None, Message::ChoosePixelGridColor),
+        ("View/Theme", ctrl | 't', Message::ChangeTheme),
+        ("Tools/Auto-assign", ctrl | 'r', Message::ClickAutoAssign),
+        ("Tools/Dedupe", ctrl | 'd', Message::ClickDedupe),
+        ("Tools/Set Characters...", ctrl | 'k', Message::ClickSetCharacters),
+        ("Tools/Check/TMS9918 Rows", Shortcut::None, Message::CheckTiles(TileFormat::Tms9918)),
+        ("Tools/Check/ZX Attribute Clashes", Shortcut::None, Message::CheckTiles(TileFormat::ZxSpectrum)),
+        ("Tools/Check/Clear Marks", Shortcut::None, Message::ClearMarks),
+        ("Tools/Verify Export...", ctrl_shift | 'r', Message::ClickVerifyExport),
+    ];
+
+    for (i, (label, shortcut, message)) in items.iter().enumerate() {
+        menu.add_emit(label, *shortcut, MenuFlag::Normal, sender.clone(), message.clone());
+        if i == 3 {
+            menu.add("File/Recent", Shortcut::None, MenuFlag::Submenu, |_| {});
+        }
+    }
+
+    for path in ["View/Show as Sheet", "View/Tile Grid", "View/Pixel Grid"].iter() {
+        let idx = menu.find_index(path);
+        menu.set_mode(idx, MenuFlag::Toggle);
+    }
+    if let Some(mut item) = menu.find_item("View/Tile Grid") {
+        item.set();
+    }
 
-    let mut btn = Button::default().with_label("Load PNG");
-    btn.emit(sender.clone(), Message::ClickOpenImage);
-    let mut btn = Button::default().with_label("Load Config");
-    btn.emit(sender.clone(), Message::ClickLoadConfig);
-    let mut btn = Button::default().with_label("Copy Config");
-    btn.emit(sender.clone(), Message::ClickExportConfig);
-    let mut btn = Button::default().with_label("Copy ASM");
-    btn.emit(sender.clone(), Message::ClickExportASM);
-    let mut btn = Button::default().with_label("Theme");
-    btn.emit(sender.clone(), Message::ChangeTheme);
+    // These need a picture to work on, so they stay disabled until one is loaded.
+    let needs_image = ["File/Save Project", "File/Save Project As...", "File/Load Config...", "File/Export", "Edit", "Tools"];
+    for path in needs_image.iter() {
+        if let Some(mut item) = menu.find_item(path) {
+            item.deactivate();
+        }
+    }
 
-    flex.end();
-    (Box::new(|_| {}), flex)
+    let widget = menu.clone();
+    (Box::new(move |m| {
+        match m {
+            Message::ImageLoaded => {
+                for path in needs_image.iter() {
+                    if let Some(mut item) = menu.find_item(path) {
+                        item.activate();
+                    }
+                }
+            }
+            Message::ViewChanged(options) => {
+                let toggles = [("View/Show as Sheet", options.sheet), ("View/Tile Grid", options.tile_grid), ("View/Pixel Grid", options.pixel_grid)];
+                for (path, on) in toggles.iter() {
+                    if let Some(mut item) = menu.find_item(path) {
+                        if *on {
+                            item.set();
+                        } else {
+                            item.clear();
+                        }
+                    }
+                }
+            }
+            Message::RecentChanged(recent) => {
+                let idx = menu.find_index("File/Recent");
+                if idx < 0 || menu.clear_submenu(idx).is_err() {
+                    return;
+                }
+
+                for (i, path) in recent.iter().enumerate() {
+                    let name = PathBuf::from(path).file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+                    // Escape the characters FLTK treats as menu syntax.
+                    let name = name.replace('\\', "\\\\").replace('&', "&&").replace('/', "\\/");
+                    menu.add_emit(&format!("File/Recent/{} {}", i + 1, name), Shortcut::None, MenuFlag::Normal, sender.clone(), Message::ClickRecent(path.clone()));
+                }
+            }
+            _ => {}
+        }
+    }), widget)
 }
 
-fn create_footer_pane(sender: Sender<Message>) -> (Box<dyn FnMut(Message)>, Flex) {
+fn create_footer_pane(_sender: Sender<Message>) -> (Box<dyn FnMut(Message)>, Flex) {
     let flex = Flex::default().column();
 
-    let _frame = Frame::default().with_label("Help: Hold Ctrl and Click to remove tile.");
+    let mut frame_status = Frame::default().with_label("No picture loaded. Load a PNG to enable the config and ASM actions.");
+
+    let flex_a = Flex::default().row();
+    let mut frame_hover = Frame::default();
+    let mut frame_stats = Frame::default();
+    flex_a.end();
+
+    let flex_b = Flex::default().row();
+    let _frame = Frame::default().with_label("Help: Ctrl+Click or Delete removes a tile, Shift+Click selects it. Arrows, Enter and hex digits assign by keyboard.");
     let _frame = Frame::default().with_label(COPYRIGHT);
+    flex_b.end();
 
     flex.end();
-    (Box::new(|_| {}), flex)
+    (Box::new(move |m| {
+        match m {
+            Message::Status(text) => frame_status.set_label(&text),
+            Message::HoverStatus(text) => frame_hover.set_label(&text),
+            Message::Stats(text) => frame_stats.set_label(&text),
+            _ => {}
+        }
+    }), flex)
 }
 
 fn create_main_pane(sender: Sender<Message>) -> Box<dyn FnMut(Message)> {
     let mut flex = Flex::default().column();
     let mut scroll = Scroll::default();
+    let mut canvas = Frame::default();
 
     scroll.end();
     flex.end();
-    Box::new(move |m| {
-        match m {
-            Message::UpdateTiles(m) => {
-                let n_cols = m.image.clone().unwrap().w() / 8;
 
-                for i in 0..scroll.children() {
-                    scroll.child(i).unwrap().set_label("??");
+    let view = Rc::new(RefCell::new(TileView {
+        tiles: Vec::new(),
+        n_rows: 0,
+        n_cols: 0,
+        labels: BTreeMap::new(),
+        selection: BTreeSet::new(),
+        tile_cursor: None,
+        marks: BTreeMap::new(),
+        options: ViewOptions::default(),
+    }));
+
+    let state = view.clone();
+    canvas.draw(move |f| state.borrow_mut().draw(f.x(), f.y(), f.w(), f.h()));
+
+    let state = view.clone();
+    let mut hover = None;
+    canvas.handle(move |f, ev| {
+        let tile = state.borrow().tile_at(event_x() - f.x(), event_y() - f.y());
+        match ev {
+            Event::Push => {
+                f.take_focus().ok();
+                if let Some((r, c)) = tile {
+                    sender.send(Message::ClickTile(r, c));
                 }
-
-                for i in 0..scroll.children() {
-                    let c = i % n_cols;
-                    let r = i / n_cols;
-                    for j in m.clone().tiles.into_iter() {
-                        if j.1.0 == r && j.1.1 == c {
-                            scroll.child(i).unwrap().set_label(&j.0);
-                        }
-                    }
+                true
+            }
+            Event::Enter | Event::Focus | Event::Unfocus => true,
+            Event::KeyDown => {
+                let message = match event_key() {
+                    Key::Left => Message::MoveTileCursor(0, -1),
+                    Key::Right => Message::MoveTileCursor(0, 1),
+                    Key::Up => Message::MoveTileCursor(-1, 0),
+                    Key::Down => Message::MoveTileCursor(1, 0),
+                    Key::Enter | Key::KPEnter => Message::AssignAtTileCursor,
+                    Key::Delete => Message::ClearAtTileCursor,
+                    Key::PageUp => Message::StepCursor(1),
+                    Key::PageDown => Message::StepCursor(-1),
+                    _ => match event_text().chars().next() {
+                        Some(digit) if digit.is_ascii_hexdigit() && !is_event_ctrl() && !is_event_alt() => Message::TypeHex(digit),
+                        _ => return false,
+                    },
+                };
+                sender.send(message);
+                true
+            }
+            Event::Move => {
+                if let (Some((r, c)), true) = (tile, tile != hover) {
+                    sender.send(Message::HoverTile(r, c));
                 }
+                hover = tile;
+                true
             }
-            DisplayImage(image) => {
-                scroll.clear();
-                scroll.begin();
+            Event::MouseWheel if is_event_ctrl() => {
+                match event_dy() {
+                    MouseWheel::Up => sender.send(Message::ChangeZoom(1)),
+                    MouseWheel::Down => sender.send(Message::ChangeZoom(-1)),
+                    _ => {}
+                }
+                true
+            }
+            _ => false,
+        }
+    });
 
-                for r in 0..image.h() / 8 {
-                    for c in 0..image.w() / 8 {
-                        let mut btn = Button::default().with_size(33, 48).with_pos(c * 34, r * 49).with_label("?");
-                        let mut image = get_tile_in_picture(r, c, &image);
-                        image.scale(32, 32, true, true);
-                        btn.set_image(Some(image));
-                        btn.emit(sender.clone(), Message::ClickTile(r, c));
+    Box::new(move |m| {
+        match m {
+            Message::UpdateTiles(m) => {
+                let mut view = view.borrow_mut();
+                view.labels = m.tiles.iter().map(|(k, &pos)| (pos, k.clone())).collect();
+                view.selection = m.selection.clone();
+                view.marks = m.marks.clone();
+
+                if m.tile_cursor != view.tile_cursor {
+                    view.tile_cursor = m.tile_cursor;
+                    if let Some((r, c)) = m.tile_cursor {
+                        scroll_into_view(&mut scroll, &view, r, c);
                     }
                 }
-                scroll.end();
+                canvas.redraw();
+            }
+            Message::ViewChanged(options) => {
+                let old = view.borrow().cell_size();
+                view.borrow_mut().set_options(options);
+                let new = view.borrow().cell_size();
+
+                // Keep roughly the same part of the picture in view while zooming.
+                let x = scroll.xposition() * new.0 / old.0;
+                let y = scroll.yposition() * new.1 / old.1;
+                layout_canvas(&mut canvas, &mut scroll, &view.borrow());
+                scroll.scroll_to(x, y);
+                scroll.redraw();
+            }
+            DisplayImage(image) => {
+                view.borrow_mut().set_image(&image);
+                layout_canvas(&mut canvas, &mut scroll, &view.borrow());
+                scroll.scroll_to(0, 0);
                 scroll.redraw();
                 flex.redraw();
             }
@@ -183,6 +736,194 @@ fn create_main_pane(sender: Sender<Message>) -> Box<dyn FnMut(Message)> {
     })
 }
 
+/// Sizes the canvas to fit every tile, keeping it where the scroll currently expects it.
+fn layout_canvas(canvas: &mut Frame, scroll: &mut Scroll, view: &TileView) {
+    let (w, h) = view.cell_size();
+    canvas.resize(scroll.x() - scroll.xposition(), scroll.y() - scroll.yposition(), view.n_cols * w, view.n_rows * h);
+}
+
+/// Scrolls just far enough for the tile at `(r, c)` to be fully visible.
+fn scroll_into_view(scroll: &mut Scroll, view: &TileView, r: i32, c: i32) {
+    let (w, h) = view.cell_size();
+    // Leave room for the scroll bars.
+    let visible_w = scroll.w() - 20;
+    let visible_h = scroll.h() - 20;
+
+    let mut x = scroll.xposition();
+    let mut y = scroll.yposition();
+    if c * w < x {
+        x = c * w;
+    } else if (c + 1) * w > x + visible_w {
+        x = (c + 1) * w - visible_w;
+    }
+    if r * h < y {
+        y = r * h;
+    } else if (r + 1) * h > y + visible_h {
+        y = (r + 1) * h - visible_h;
+    }
+
+    scroll.scroll_to(x.max(0), y.max(0));
+    scroll.redraw();
+}
+
+/// How the tile view is drawn.
+#[derive(Clone, Copy, Debug)]
+struct ViewOptions {
+    zoom: i32,
+    /// Draws the picture contiguously with labels on top instead of one spaced cell per tile.
+    sheet: bool,
+    tile_grid: bool,
+    pixel_grid: bool,
+    tile_grid_color: Color,
+    pixel_grid_color: Color,
+}
+
+impl Default for ViewOptions {
+    fn default() -> Self {
+        ViewOptions {
+            zoom: 4,
+            sheet: false,
+            tile_grid: true,
+            pixel_grid: false,
+            tile_grid_color: Color::Dark3,
+            pixel_grid_color: Color::Light1,
+        }
+    }
+}
+
+/// What the tile view shows, shared between the main pane and the canvas callbacks.
+struct TileView {
+    tiles: Vec<RgbImage>,
+    n_rows: i32,
+    n_cols: i32,
+    labels: BTreeMap<(i32, i32), String>,
+    selection: BTreeSet<(i32, i32)>,
+    tile_cursor: Option<(i32, i32)>,
+    marks: BTreeMap<(i32, i32), u8>,
+    options: ViewOptions,
+}
+
+const LABEL_HEIGHT: i32 = 16;
+
+impl TileView {
+    fn set_image(&mut self, image: &Sheet) {
+        self.n_rows = image.h() / 8;
+        self.n_cols = image.w() / 8;
+        self.tiles.clear();
+        for r in 0..self.n_rows {
+            for c in 0..self.n_cols {
+                self.tiles.push(get_tile_in_picture(r, c, image));
+            }
+        }
+        self.set_options(self.options);
+    }
+
+    fn set_options(&mut self, options: ViewOptions) {
+        self.options = options;
+        let size = self.tile_size();
+        for tile in self.tiles.iter_mut() {
+            tile.scale(size, size, false, true);
+        }
+    }
+
+    fn tile_size(&self) -> i32 {
+        8 * self.options.zoom
+    }
+
+    /// The space one tile takes, including its label and gap when not in sheet mode.
+    fn cell_size(&self) -> (i32, i32) {
+        let size = self.tile_size();
+        if self.options.sheet {
+            (size, size)
+        } else {
+            (size + 2, size + LABEL_HEIGHT + 1)
+        }
+    }
+
+    fn tile_at(&self, x: i32, y: i32) -> Option<(i32, i32)> {
+        let (w, h) = self.cell_size();
+        if x < 0 || y < 0 || x >= self.n_cols * w || y >= self.n_rows * h {
+            return None;
+        }
+        Some((y / h, x / w))
+    }
+
+    fn draw(&mut self, x: i32, y: i32, w: i32, h: i32) {
+        draw::draw_rect_fill(x, y, w, h, Color::BackGround);
+        draw::set_font(Font::Helvetica, if self.options.zoom < 3 { 8 } else { 11 });
+
+        let (cell_w, cell_h) = self.cell_size();
+        let size = self.tile_size();
+        let sheet = self.options.sheet;
+
+        for r in 0..self.n_rows {
+            for c in 0..self.n_cols {
+                let cell_x = x + c * cell_w;
+                let cell_y = y + r * cell_h;
+                if !draw::not_clipped(cell_x, cell_y, cell_w, cell_h) {
+                    continue;
+                }
+
+                // In sheet mode the tile fills its cell; otherwise it sits one pixel in.
+                let (tile_x, tile_y) = if sheet { (cell_x, cell_y) } else { (cell_x + 1, cell_y) };
+                let selected = self.selection.contains(&(r, c));
+
+                if selected && !sheet {
+                    draw::draw_rect_fill(cell_x, cell_y, cell_w, cell_h, Color::Selection);
+                }
+
+                self.tiles[(r * self.n_cols + c) as usize].draw(tile_x, tile_y, size, size);
+
+                if self.options.pixel_grid && self.options.zoom > 1 {
+                    draw::set_draw_color(self.options.pixel_grid_color);
+                    for i in 1..8 {
+                        draw::draw_xyline(tile_x, tile_y + i * self.options.zoom, tile_x + size - 1);
+                        draw::draw_yxline(tile_x + i * self.options.zoom, tile_y, tile_y + size - 1);
+                    }
+                }
+
+                if self.options.tile_grid {
+                    draw::draw_rect_with_color(tile_x, tile_y, size, size, self.options.tile_grid_color);
+                }
+
+                if selected && sheet {
+                    draw::draw_rect_with_color(tile_x, tile_y, size, size, Color::Selection);
+                    draw::draw_rect_with_color(tile_x + 1, tile_y + 1, size - 2, size - 2, Color::Selection);
+                }
+
+                if let Some(&rows) = self.marks.get(&(r, c)) {
+                    let zoom = self.options.zoom;
+                    for i in (0..8).filter(|i| rows & 1 << i != 0) {
+                        draw::draw_rect_with_color(tile_x, tile_y + i * zoom, size, zoom, Color::Magenta);
+                    }
+                }
+
+                if self.tile_cursor == Some((r, c)) {
+                    draw::set_draw_color(Color::Red);
+                    draw::set_line_style(draw::LineStyle::Solid, 2);
+                    draw::draw_rect(tile_x + 1, tile_y + 1, size - 1, size - 1);
+                    draw::set_line_style(draw::LineStyle::Solid, 0);
+                }
+
+                match (self.labels.get(&(r, c)), sheet) {
+                    (Some(label), true) => {
+                        // Overlaid labels get a backdrop so they stay readable on any picture.
+                        let (text_w, text_h) = draw::measure(label, false);
+                        draw::draw_rect_fill(tile_x, tile_y, text_w + 2, text_h, Color::Black);
+                        draw::set_draw_color(Color::White);
+                        draw::draw_text2(label, tile_x + 1, tile_y, text_w, text_h, Align::Left);
+                    }
+                    (label, false) => {
+                        draw::set_draw_color(Color::ForeGround);
+                        draw::draw_text2(label.map(|x| x.as_str()).unwrap_or("??"), cell_x, tile_y + size, cell_w, LABEL_HEIGHT, Align::Center);
+                    }
+                    (None, true) => {}
+                }
+            }
+        }
+    }
+}
+
 fn create_bottom_pane(sender: Sender<Message>) -> (Box<dyn FnMut(Message)>, Flex) {
     let flex = Flex::default().column();
 
@@ -190,7 +931,7 @@ fn create_bottom_pane(sender: Sender<Message>) -> (Box<dyn FnMut(Message)>, Flex
 
     let mut frame_cursor = Frame::default().with_label("0x00");
     let mut input_cursor = IntInput::default();
-    let frame = Frame::default().with_label("Prefix: ");
+    let _frame = Frame::default().with_label("Prefix: ");
     let mut input = Input::default();
     input.set_value("Tile_");
 
@@ -198,10 +939,10 @@ fn create_bottom_pane(sender: Sender<Message>) -> (Box<dyn FnMut(Message)>, Flex
 
     let flex_b = Flex::default().row();
 
-    let btn = Button::default().with_label("+1 before");
-    let btn = Button::default().with_label("+1 after");
-    let btn = Button::default().with_label("-1 before");
-    let btn = Button::default().with_label("-1 after");
+    let _btn = Button::default().with_label("+1 before");
+    let _btn = Button::default().with_label("+1 after");
+    let _btn = Button::default().with_label("-1 before");
+    let _btn = Button::default().with_label("-1 after");
 
     flex_b.end();
 
@@ -214,95 +955,810 @@ fn create_bottom_pane(sender: Sender<Message>) -> (Box<dyn FnMut(Message)>, Flex
             sender.send(Message::CursorEdited(cursor));
         }
 
-        match m {
-            Message::CursorEdited(x) => {
-                frame_cursor.set_label(&format!("{:#04x}", x));
-                input_cursor.set_value(&format!("{}", x))
-            }
-            _ => {}
+        if let Message::CursorEdited(x) = m {
+            frame_cursor.set_label(&format!("{:#04x}", x));
+            input_cursor.set_value(&format!("{}", x))
         }
     }), flex)
 }
 
 
 #[derive(Clone, Debug)]
+#[allow(clippy::large_enum_variant)]
 enum Message {
     Nothing,
     ChangeTheme,
     ClickOpenImage,
     ImageLoaded,
-    DisplayImage(PngImage),
+    Status(String),
+    DisplayImage(Sheet),
     ClickTile(i32, i32),
+    MoveTileCursor(i32, i32),
+    AssignAtTileCursor,
+    ClearAtTileCursor,
+    StepCursor(i32),
+    TypeHex(char),
+    HoverTile(i32, i32),
+    HoverStatus(String),
+    Stats(String),
     CursorEdited(i32),
     UpdateTiles(Model),
     ClickExportConfig,
-    ClickExportASM,
+    ClickExportASM(TileFormat),
+    ClickExportCharmap,
+    ClickSetCharacters,
     ClickLoadConfig,
+    ClickOpenProject,
+    ClickImportRom,
+    ClickExportRom,
+    ClickExportTiles,
+    ClickExportCart(fantasy::Console),
+    ClickOpenChr,
+    ClickImportAsm,
+    ClickSaveSheet,
+    ClickSaveProject(bool),
+    ClickRecent(String),
+    RecentChanged(Vec<String>),
+    ClickQuit,
+    ClickUndo,
+    ClickRedo,
+    ClickSelectAll,
+    ClickSelectNone,
+    ClickClear,
+    ClickAutoAssign,
+    ClickDedupe,
+    ClickVerifyExport,
+    CheckTiles(TileFormat),
+    ClearMarks,
+    ChangeZoom(i32),
+    ToggleSheet,
+    ToggleTileGrid,
+    TogglePixelGrid,
+    ChooseTileGridColor,
+    ChoosePixelGridColor,
+    ViewChanged(ViewOptions),
 }
 
 #[derive(Clone, Debug)]
 struct Model {
     sender: Sender<Message>,
     dark_mode: bool,
-    image: Option<PngImage>,
+    image: Option<Sheet>,
+    image_path: Option<String>,
+    /// The ROM the sheet was imported from.
+    rom: Option<(String, Rom)>,
+    /// The colours of the sheet in index order, for sheets decoded from tile data.
+    /// Pictures have none and are numbered against the colours of their assigned tiles, see `sheet_palette`.
+    palette: Option<Vec<[u8; 3]>>,
+    project_path: Option<String>,
     tiles: BTreeMap<String, (i32, i32)>,
+    /// Text for tiles, keyed by tile number.
+    charmap: BTreeMap<usize, String>,
     cursor: i32,
     prefix: String,
+    hover: Option<(i32, i32)>,
+    /// The tile the keyboard works on, highlighted in the tile view.
+    tile_cursor: Option<(i32, i32)>,
+    /// Hex digits typed so far to jump the index cursor.
+    hex_entry: String,
+    dirty: bool,
+    selection: BTreeSet<(i32, i32)>,
+    /// Rows of tiles that break a format's rules, as a bit mask per tile with row 0 in bit 0.
+    marks: BTreeMap<(i32, i32), u8>,
+    undo: Vec<BTreeMap<String, (i32, i32)>>,
+    redo: Vec<BTreeMap<String, (i32, i32)>>,
+    recent: Vec<String>,
+    view: ViewOptions,
 }
 
 impl Model {
-    fn load_png(&mut self) {
-        let path = file_chooser("Choose a picture", "*.png", "", false);
-        match path {
-            Some(path) => {
-                self.image = PngImage::load(&path).ok();
-                self.sender.send(Message::ImageLoaded);
+    fn new(sender: Sender<Message>) -> Model {
+        Model {
+            sender,
+            dark_mode: false,
+            image: None,
+            image_path: None,
+            rom: None,
+            palette: None,
+            project_path: None,
+            tiles: BTreeMap::new(),
+            charmap: BTreeMap::new(),
+            cursor: 0,
+            prefix: String::from("Tile_"),
+            hover: None,
+            tile_cursor: None,
+            hex_entry: String::new(),
+            dirty: false,
+            selection: BTreeSet::new(),
+            marks: BTreeMap::new(),
+            undo: Vec::new(),
+            redo: Vec::new(),
+            recent: load_recent(),
+            view: ViewOptions::default(),
+        }
+    }
+
+    fn load_png(&mut self) -> Result<(), String> {
+        match file_chooser("Choose a picture", "*.png", "", false) {
+            Some(path) => self.open_image(path),
+            None => Ok(()),
+        }
+    }
+
+    fn open_image(&mut self, path: String) -> Result<(), String> {
+        let image = PngImage::load(&path).map_err(|e| format!("Could not load {}: {}", path, e))?;
+
+        if image.data_w() < 8 || image.data_h() < 8 {
+            return Err(format!("{} is smaller than a single 8x8 tile.", path));
+        }
+
+        self.image = Some(Sheet::from_raw(image.data_w(), image.data_h(), image.depth() as usize, &image.to_rgb_data()));
+        self.image_path = Some(path.clone());
+        self.rom = None;
+        self.palette = None;
+        // The old assignments may lie outside the new picture, and belong to the project it came from.
+        self.tiles.clear();
+        self.charmap.clear();
+        self.marks.clear();
+        self.selection.clear();
+        self.undo.clear();
+        self.redo.clear();
+        self.cursor = 0;
+        self.project_path = None;
+        self.dirty = false;
+        self.add_recent(path);
+        self.sender.send(Message::ImageLoaded);
+        Ok(())
+    }
+
+    /// Shows the CHR-ROM of an iNES ROM as a sheet 16 tiles wide, each tile named after its position in the ROM.
+    fn open_rom(&mut self, path: String, palette: Vec<[u8; 3]>) -> Result<(), String> {
+        let data = fs::read(&path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+        let rom = Rom::parse(data).map_err(|e| format!("{}: {}", path, e))?;
+
+        if rom.chr_size == 0 {
+            return Err(format!("{} has no CHR-ROM; its graphics are in CHR-RAM.", path));
+        }
+
+        let tiles = chr::decode_tiles(rom.chr(), TileFormat::Nes);
+        self.show_tiles(&tiles, palette);
+        self.rom = Some((path.clone(), rom));
+        self.add_recent(path);
+        self.sender.send(Message::ImageLoaded);
+        Ok(())
+    }
+
+    /// Shows `length` bytes of raw tile data from `offset` in a file, or everything after it when `length` is zero.
+    fn open_chr(&mut self, path: String, offset: usize, length: usize, format: TileFormat, palette: usize) -> Result<(), String> {
+        let data = fs::read(&path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+        let end = if length == 0 { data.len() } else { offset.saturating_add(length) };
+        if offset > data.len() || end > data.len() {
+            return Err(format!("{} is only {} bytes long.", path, data.len()));
+        }
+
+        let tiles = chr::decode_tiles(&data[offset..end], format);
+        if tiles.is_empty() {
+            return Err(format!("There is not a whole {} byte tile there.", format.tile_bytes()));
+        }
+
+        self.show_tiles(&tiles, chr::preview_palette(&chr::PREVIEW_PALETTES[palette].1, format.colors()));
+        self.rom = None;
+        self.sender.send(Message::ImageLoaded);
+        Ok(())
+    }
+
+    /// Rebuilds tiles from `.db` pattern data such as `export_asm` writes. Each tile is named after the
+    /// address it was assembled at, and a prefix shared by all the labels becomes the project's prefix.
+    fn open_asm(&mut self, path: String, palette: Vec<[u8; 3]>) -> Result<(), String> {
+        let text = fs::read_to_string(&path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+        let parsed = asm::parse_patterns(&text, TileFormat::Nes.tile_bytes()).map_err(|e| format!("{}: {}", path, e))?;
+        if parsed.is_empty() {
+            return Err(format!("{} has no tile data.", path));
+        }
+
+        let tiles = parsed.iter().map(|x| TileFormat::Nes.decode(&x.bytes)).collect::<Vec<[u8; 64]>>();
+        self.show_tiles(&tiles, palette);
+        self.rom = None;
+
+        self.tiles.clear();
+        for (i, tile) in parsed.iter().enumerate() {
+            self.tiles.insert(format!("{:#04x}", tile.index), (i as i32 / 16, i as i32 % 16));
+        }
+        self.cursor = parsed.iter().map(|x| x.index as i32 + 1).max().unwrap_or(0);
+
+        let prefixes = parsed.iter().map(|x| {
+            let key = format!("{:#04x}", x.index);
+            x.label.as_ref().and_then(|label| label.strip_suffix(&key).or_else(|| label.strip_suffix(&key.replace("0x", "$"))))
+        }).collect::<Option<BTreeSet<&str>>>();
+        if let Some(prefixes) = prefixes {
+            if prefixes.len() == 1 {
+                self.prefix = prefixes.iter().next().unwrap().to_string();
+            }
+        }
+
+        self.add_recent(path);
+        self.sender.send(Message::ImageLoaded);
+        Ok(())
+    }
+
+    /// Replaces the picture with decoded tiles 16 to a row, each named after its position in the data.
+    fn show_tiles(&mut self, tiles: &[[u8; 64]], palette: Vec<[u8; 3]>) {
+        self.image = Some(Sheet::from_tiles(tiles, 16, &palette));
+        self.image_path = None;
+        self.palette = Some(palette);
+        self.charmap.clear();
+        self.marks.clear();
+        self.selection.clear();
+        self.undo.clear();
+        self.redo.clear();
+
+        self.tiles.clear();
+        for i in 0..tiles.len() as i32 {
+            self.tiles.insert(format!("{:#04x}", i), (i / 16, i % 16));
+        }
+        self.cursor = tiles.len() as i32;
+        self.project_path = None;
+        self.dirty = false;
+    }
+
+    /// Saves the sheet as a PNG with a project next to it, so decoded graphics can be edited like any picture.
+    fn save_sheet(&mut self) -> Result<Option<String>, String> {
+        let image = self.image.clone().ok_or("Nothing to save.")?;
+        let path = match save_file_chooser("Save sheet as PNG", "*.png") {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        image.save_png(&path)?;
+
+        let project = PathBuf::from(&path).with_extension("ltp").to_string_lossy().into_owned();
+        self.image_path = Some(path.clone());
+        self.write_project(&project)?;
+        Ok(Some(format!("Saved {} and {}.", path, project)))
+    }
+
+    /// Projects are plain `key=value` lines holding the picture path, the tile config and the editor state.
+    fn open_project(&mut self, path: String) -> Result<(), String> {
+        let content = fs::read_to_string(&path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+        let mut values = BTreeMap::new();
+        for line in content.lines() {
+            if let Some((key, value)) = line.split_once('=') {
+                values.insert(key.trim(), value.trim());
             }
+        }
+
+        let palette = match values.get("palette") {
+            Some(x) => Some(verify::parse_palette(x).ok_or(format!("{} has a malformed palette.", path))?),
+            None => None,
+        };
+
+        match (values.get("image"), values.get("rom")) {
+            (Some(image), _) => {
+                self.open_image(image.to_string())?;
+                self.palette = palette;
+            }
+            (None, Some(rom)) => {
+                let palette = palette.unwrap_or_else(|| chr::PREVIEW_PALETTES[0].1.to_vec());
+                self.open_rom(rom.to_string(), palette)?
+            }
+            (None, None) => return Err(format!("{} does not name a picture.", path)),
+        }
+
+        self.tiles.clear();
+        self.undo.clear();
+        self.redo.clear();
+        self.import_config(values.get("config").unwrap_or(&"").to_string())?;
+        self.cursor = values.get("cursor").and_then(|x| x.parse::<i32>().ok()).unwrap_or(0);
+        if let Some(prefix) = values.get("prefix") {
+            self.prefix = prefix.to_string();
+        }
+        self.charmap = match values.get("charmap") {
+            Some(x) => charmap::from_project(x).ok_or(format!("{} has a malformed charmap.", path))?,
+            None => BTreeMap::new(),
+        };
+
+        self.project_path = Some(path.clone());
+        self.dirty = false;
+        self.add_recent(path);
+        Ok(())
+    }
+
+    fn save_project(&mut self, save_as: bool) -> Result<(), String> {
+        match (&self.image, &self.image_path, &self.rom) {
+            (None, _, _) => return Err(String::from("Load a PNG before saving a project.")),
+            (Some(_), None, None) => return Err(String::from("Save the sheet as a PNG before saving a project.")),
+            _ => {}
+        }
+
+        let path = match (&self.project_path, save_as) {
+            (Some(path), false) => path.clone(),
+            _ => match save_file_chooser("Save project", "*.ltp") {
+                Some(path) => path,
+                None => return Ok(()),
+            },
+        };
+        self.write_project(&path)
+    }
+
+    fn write_project(&mut self, path: &str) -> Result<(), String> {
+        let mut content = match (&self.image_path, &self.rom) {
+            (Some(image), _) => format!("image={}\n", image),
+            (None, Some((rom, _))) => format!("rom={}\n", rom),
+            (None, None) => String::new(),
+        };
+        if let Some(palette) = &self.palette {
+            let colors = palette.iter().map(|x| format!("{:02x}{:02x}{:02x}", x[0], x[1], x[2])).collect::<Vec<String>>();
+            content.push_str(&format!("palette={}\n", colors.join(",")));
+        }
+        content.push_str(&format!("prefix={}\ncursor={}\nconfig={}\n", self.prefix, self.cursor, self.config_string()));
+        if !self.charmap.is_empty() {
+            content.push_str(&format!("charmap={}\n", charmap::to_project(&self.charmap)));
+        }
+        fs::write(path, content).map_err(|e| format!("Could not write {}: {}", path, e))?;
+
+        self.project_path = Some(String::from(path));
+        self.dirty = false;
+        self.add_recent(String::from(path));
+        Ok(())
+    }
+
+    fn add_recent(&mut self, path: String) {
+        self.recent.retain(|x| *x != path);
+        self.recent.insert(0, path);
+        self.recent.truncate(MAX_RECENT);
+
+        // The list is a convenience, so failing to persist it is not worth an error.
+        if let Some(file) = recent_file() {
+            fs::write(file, self.recent.join("\n")).ok();
+        }
+    }
+
+    /// Remembers the current tiles so the next change can be undone.
+    fn checkpoint(&mut self) {
+        self.undo.push(self.tiles.clone());
+        if self.undo.len() > MAX_UNDO {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    fn undo(&mut self) {
+        if let Some(tiles) = self.undo.pop() {
+            self.redo.push(std::mem::replace(&mut self.tiles, tiles));
+            self.dirty = true;
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some(tiles) = self.redo.pop() {
+            self.undo.push(std::mem::replace(&mut self.tiles, tiles));
+            self.dirty = true;
+        }
+    }
+
+    fn move_tile_cursor(&mut self, dr: i32, dc: i32) {
+        let image = match &self.image {
+            Some(image) => image,
             None => return,
         };
+
+        self.tile_cursor = Some(match self.tile_cursor {
+            Some((r, c)) => ((r + dr).clamp(0, image.h() / 8 - 1), (c + dc).clamp(0, image.w() / 8 - 1)),
+            None => (0, 0),
+        });
     }
 
-    fn export_asm(&mut self) {
-        let keys = self.tiles.keys();
-        let mut result = String::from("");
+    /// Moves the tile cursor to the next tile in reading order, stopping at the last one.
+    fn advance_tile_cursor(&mut self) {
+        if let (Some(image), Some((r, c))) = (&self.image, self.tile_cursor) {
+            let n_cols = image.w() / 8;
+            if c + 1 < n_cols {
+                self.tile_cursor = Some((r, c + 1));
+            } else if r + 1 < image.h() / 8 {
+                self.tile_cursor = Some((r + 1, 0));
+            }
+        }
+    }
 
-        let image = self.image.clone().unwrap();
+    fn select_all(&mut self) {
+        if let Some(image) = &self.image {
+            for r in 0..image.h() / 8 {
+                for c in 0..image.w() / 8 {
+                    self.selection.insert((r, c));
+                }
+            }
+        }
+    }
 
-        for k in keys {
-            result.push_str(&format!("\n;\n       .org {} * 16\n        {}{}:{}\n", k.replace("0x", "$"), self.prefix, k, tile_to_pattern(
-                (get_tile_in_picture(self.tiles[k].0,
-                                     self.tiles[k].1, &image)))))
+    /// Clears the selected tiles, or every tile when nothing is selected.
+    fn clear_selected(&mut self) {
+        if self.selection.is_empty() {
+            if !self.tiles.is_empty() {
+                self.tiles.clear();
+                self.dirty = true;
+            }
+        } else {
+            for (r, c) in self.selection.clone() {
+                self.clear_tile(r, c);
+            }
+        }
+    }
+
+    /// Gives the unassigned tiles of the selection, or of the whole picture when nothing is
+    /// selected, consecutive indices from the cursor in reading order. Returns how many were assigned.
+    fn auto_assign(&mut self) -> usize {
+        let image = match &self.image {
+            Some(image) => image,
+            None => return 0,
+        };
+
+        let mut targets = Vec::new();
+        for r in 0..image.h() / 8 {
+            for c in 0..image.w() / 8 {
+                if self.selection.is_empty() || self.selection.contains(&(r, c)) {
+                    targets.push((r, c));
+                }
+            }
+        }
+
+        let mut n = 0;
+        for (r, c) in targets {
+            if self.tiles.values().any(|&pos| pos == (r, c)) {
+                continue;
+            }
+            while self.tiles.contains_key(&format!("{:#04x}", self.cursor)) {
+                self.cursor += 1;
+            }
+            self.set_tile(format!("{:#04x}", self.cursor), r, c);
+            self.cursor += 1;
+            n += 1;
+        }
+        n
+    }
+
+    /// Unassigns tiles whose pixels repeat an earlier assigned tile. Returns how many were removed.
+    fn dedupe(&mut self) -> usize {
+        let image = match &self.image {
+            Some(image) => image,
+            None => return 0,
+        };
+
+        let mut seen = BTreeSet::new();
+        let mut duplicates = Vec::new();
+        for (k, &(r, c)) in self.tiles.iter() {
+            if image.tile(r, c).map(|x| !seen.insert(x)).unwrap_or(false) {
+                duplicates.push(k.clone());
+            }
+        }
+
+        for k in duplicates.iter() {
+            self.tiles.remove(k);
+        }
+        if !duplicates.is_empty() {
+            self.dirty = true;
         }
+        duplicates.len()
+    }
+
+    fn export_asm(&mut self, format: TileFormat) -> Result<(), String> {
+        let result = self.asm_string(format)?;
+        println!("{}", result);
+        copy(&result);
+        Ok(())
+    }
+
+    fn asm_string(&self, format: TileFormat) -> Result<String, String> {
+        let image = self.image.as_ref().ok_or("Load a PNG before exporting ASM.")?;
+        asm_patterns(image, &self.tiles, &self.sheet_palette(), format, &self.prefix)
+    }
 
-        println!("{};", result);
-        copy(&format!("{};", result));
+    /// The colours exported indices stand for: the palette of a decoded sheet, or every colour
+    /// of the assigned tiles of a picture in ascending red.
+    fn sheet_palette(&self) -> Vec<[u8; 3]> {
+        match (&self.palette, &self.image) {
+            (Some(palette), _) => palette.clone(),
+            (None, Some(image)) => verify::picture_palette(image, &self.tiles),
+            (None, None) => Vec::new(),
+        }
     }
 
-    fn import_config(&mut self, cfg: String) {
-        let tiles = cfg.split(",").filter(|x| x.len() > 3);
+    /// Decodes an export back into tiles and compares them with the sheet. `path` names an exported
+    /// `.asm` file, a raw CHR binary or a ROM; without one the ASM export is checked as it would be copied.
+    /// Returns the tiles that differ and how many were checked.
+    fn verify_export(&self, path: Option<&str>, compression: compress::Compression) -> Result<(Vec<verify::Mismatch>, usize), String> {
+        let image = self.image.as_ref().ok_or("Load a picture before verifying an export.")?;
+        let exported = match path {
+            Some(path) => verify::read_export(path, compression)?,
+            None => verify::asm_export(image, &self.tiles, &self.sheet_palette(), &self.prefix)?,
+        };
+        let mismatches = verify::check(image, &self.tiles, &exported, self.palette.as_deref())?;
+        Ok((mismatches, self.tiles.len()))
+    }
+
+    /// Writes the assigned tiles into the CHR-ROM slots their names give, then saves a patched ROM or a patch.
+    /// Returns a report of what changed, or `None` when the user cancelled.
+    fn export_rom(&mut self) -> Result<Option<String>, String> {
+        self.image.as_ref().ok_or("Load a picture before writing to a ROM.")?;
+        let rom = match &self.rom {
+            Some((_, rom)) => rom.clone(),
+            None => {
+                let path = match file_chooser("Choose the ROM to write to", "*.nes", "", false) {
+                    Some(path) => path,
+                    None => return Ok(None),
+                };
+                let data = fs::read(&path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+                Rom::parse(data).map_err(|e| format!("{}: {}", path, e))?
+            }
+        };
+
+        let tiles = self.encode_tiles(TileFormat::Nes)?.tiles.into_iter().collect::<Vec<(usize, Vec<u8>)>>();
+        let (patched, changed) = rom.with_tiles(&tiles)?;
 
-        for tile in tiles {
-            let temp = tile.split(":").collect::<Vec<&str>>();
-            let key = temp[0];
-            let temp = temp[1].split("_").collect::<Vec<&str>>();
-            println!("{} {} {}", key, 0, 0);
-            self.set_tile(String::from(key), temp[0].parse::<i32>().unwrap_or(0), temp[1].parse::<i32>().unwrap_or(0));
+        let (output, pattern) = match choice_default("Save the tiles as", "Patched ROM", "IPS patch", "BPS patch") {
+            0 => (patched, "*.nes"),
+            1 => (patch::ips(&rom.data, &patched)?, "*.ips"),
+            2 => (patch::bps(&rom.data, &patched)?, "*.bps"),
+            _ => return Ok(None),
+        };
+        let path = match save_file_chooser("Save", pattern) {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        fs::write(&path, output).map_err(|e| format!("Could not write {}: {}", path, e))?;
+
+        let mut names = changed.iter().take(32).map(|i| format!("{:#04x}", i)).collect::<Vec<String>>();
+        if changed.len() > names.len() {
+            names.push(format!("and {} more", changed.len() - names.len()));
         }
+        Ok(Some(match changed.len() {
+            0 => format!("Wrote {} tiles to {}, none differ from the original ROM.", tiles.len(), path),
+            n => format!("Wrote {} tiles to {}, {} differ from the original ROM: {}.", tiles.len(), path, n, names.join(", ")),
+        }))
+    }
+
+    /// Encodes the assigned tiles in `format` and saves them as `output`, along with whatever palette or
+    /// tables the format needs. C source comes with a header next to it, and binary with a file for each
+    /// palette and table. The tiles are compressed first unless `compression` is `None`.
+    /// Returns a report of what was written, or `None` when the user cancelled.
+    fn export_tiles(&mut self, format: TileFormat, output: export::Output, compression: compress::Compression) -> Result<Option<String>, String> {
+        let mut export = self.encode_tiles(format)?;
+        export.compress(compression, format);
+        let address = match (output, export::load_address(format)) {
+            (export::Output::Binary(true), Some(address)) => Some(address),
+            (export::Output::Binary(true), None) => return Err(format!("{} tiles are not loaded from a file with a load address.", format.name())),
+            _ => None,
+        };
+
+        let path = match save_file_chooser("Export tiles", output.extension()) {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let mut written = vec![path.clone()];
+        let name = export::identifier(&PathBuf::from(&path).file_stem().map(|x| x.to_string_lossy().into_owned()).unwrap_or_default());
+
+        match output {
+            export::Output::Asm(syntax) => {
+                let text = export::text(&export, format, syntax, &self.prefix)?;
+                fs::write(&path, text).map_err(|e| format!("Could not write {}: {}", path, e))?;
+            }
+            export::Output::C => {
+                let header_path = PathBuf::from(&path).with_extension("h");
+                let (source, header) = export::c_source(&export, format, &name, &header_path.file_name().unwrap_or_default().to_string_lossy())?;
+                let header_path = header_path.to_string_lossy().into_owned();
+                fs::write(&path, source).map_err(|e| format!("Could not write {}: {}", path, e))?;
+                fs::write(&header_path, header).map_err(|e| format!("Could not write {}: {}", header_path, e))?;
+                written.push(header_path);
+            }
+            export::Output::Rust => {
+                let source = export::rust_source(&export, format, &name)?;
+                fs::write(&path, source).map_err(|e| format!("Could not write {}: {}", path, e))?;
+            }
+            export::Output::Binary(_) => {
+                let mut data = export.tile_data(format);
+                if let Some(address) = address {
+                    data.splice(0..0, address.to_le_bytes());
+                }
+                fs::write(&path, data).map_err(|e| format!("Could not write {}: {}", path, e))?;
+
+                let mut extras = Vec::new();
+                if let Some((color_format, palette)) = &export.palette {
+                    extras.push(("pal", color_format.encode_palette(palette)));
+                }
+                for table in export.tables.iter() {
+                    extras.push((table.extension, table.bytes.clone()));
+                }
+                for (extension, mut bytes) in extras {
+                    if let Some(address) = address.filter(|_| export::addresses_extras(format)) {
+                        bytes.splice(0..0, address.to_le_bytes());
+                    }
+                    let extra = PathBuf::from(&path).with_extension(extension).to_string_lossy().into_owned();
+                    fs::write(&extra, bytes).map_err(|e| format!("Could not write {}: {}", extra, e))?;
+                    written.push(extra);
+                }
+            }
+        }
+
+        let palette_note = match &export.palette {
+            Some((_, palette)) => format!(" with a {} color palette", palette.len()),
+            None => String::new(),
+        };
+        let compression_note = match &export.compressed {
+            Some((compression, bytes)) => format!(" {} compressed them to {}.", compression.name(), compress::report(bytes.len(), export::binary(&export.tiles, format.tile_bytes()).len())),
+            None => String::new(),
+        };
+        let file = PathBuf::from(&path).file_name().map(|x| x.to_string_lossy().into_owned()).unwrap_or_default();
+        let load_note = match address.and_then(|_| export::load_command(format, &file)) {
+            Some(command) => format!(" Load it with {}.", command),
+            None => String::new(),
+        };
+        Ok(Some(format!("Wrote {} {} tiles{} to {}.{}{}", export.tiles.len(), format.name(), palette_note, written.join(" and "), compression_note, load_note)))
+    }
+
+    /// Writes the assigned tiles into a fantasy console cart as the sprites their numbers give, keeping
+    /// the rest of the cart when it already exists. Returns a report, or `None` when the user cancelled.
+    fn export_cart(&mut self, console: fantasy::Console) -> Result<Option<String>, String> {
+        let image = self.image.as_ref().ok_or("Load a picture before writing a cart.")?;
+        let mut sprites = BTreeMap::new();
+        for (k, &(r, c)) in self.tiles.iter() {
+            let i = parse_index(k).ok_or(format!("{} is not a tile number.", k))?;
+            sprites.insert(i, image.tile(r, c).map_err(|e| format!("{}: {}", k, e))?);
+        }
+
+        let path = match save_file_chooser(&format!("Write to {} cart", console.name()), console.extension()) {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let existing = match PathBuf::from(&path).exists() {
+            true => Some(fs::read_to_string(&path).map_err(|e| format!("Could not read {}: {}", path, e))?),
+            false => None,
+        };
+
+        let cart = console.write(existing.as_deref(), &sprites)?;
+        fs::write(&path, cart).map_err(|e| format!("Could not write {}: {}", path, e))?;
+        Ok(Some(format!("Wrote {} sprites into {}.", sprites.len(), path)))
+    }
+
+    /// Marks the rows of every tile on the sheet that `format` cannot show. Returns how many tiles have marks.
+    fn check_tiles(&mut self, format: TileFormat) -> usize {
+        self.marks.clear();
+        if let Some(image) = &self.image {
+            for r in 0..image.h() / 8 {
+                for c in 0..image.w() / 8 {
+                    let tile = match image.tile(r, c) {
+                        Ok(tile) => tile,
+                        Err(_) => continue,
+                    };
+                    let rows = match format {
+                        TileFormat::Tms9918 => tms::row_violations(&tile),
+                        // An attribute covers the whole tile, so a clash marks all of it.
+                        TileFormat::ZxSpectrum if zx::clash(&tile) => 0xff,
+                        _ => 0,
+                    };
+                    if rows != 0 {
+                        self.marks.insert((r, c), rows);
+                    }
+                }
+            }
+        }
+        self.marks.len()
+    }
+
+    /// Encodes the assigned tiles in `format`, keyed by tile number, with the palette or tables it needs.
+    fn encode_tiles(&self, format: TileFormat) -> Result<export::Export, String> {
+        let image = self.image.as_ref().ok_or("Load a picture before exporting tiles.")?;
+        convert::encode_tiles(image, &self.tiles, &self.sheet_palette(), format)
+    }
+
+    fn import_config(&mut self, cfg: String) -> Result<(), String> {
+        let image = self.image.as_ref().ok_or("Load a PNG before loading a config.")?;
+
+        // Parse everything first so a bad entry leaves the current tiles untouched.
+        let parsed = parse_config(&cfg, image)?;
+        for (key, r, c) in parsed {
+            self.set_tile(key, r, c);
+        }
+        Ok(())
+    }
+
+    /// Describes the tile at `(r, c)` for the status bar.
+    fn describe_tile(&self, r: i32, c: i32) -> String {
+        let image = match &self.image {
+            Some(image) => image,
+            None => return String::new(),
+        };
+
+        let name = match self.tiles.iter().find(|(_, &pos)| pos == (r, c)) {
+            Some((k, _)) => match parse_index(k).and_then(|i| self.charmap.get(&i)) {
+                Some(text) => format!("{}{} {:?}", self.prefix, k, text),
+                None => format!("{}{}", self.prefix, k),
+            },
+            None => String::from("unassigned"),
+        };
+
+        format!("Tile ({}, {}) at +{},+{}: {}, {} colors", r, c, c * 8, r * 8, name, image.tile(r, c).map(|x| count_colors(&x)).unwrap_or(0))
+    }
+
+    /// Summarises the assigned tiles and the bank the cursor is in.
+    fn stats(&self) -> String {
+        let indices = self.tiles.keys().filter_map(|k| parse_index(k)).collect::<Vec<usize>>();
+        let bank = self.cursor / 256;
+        let used = indices.iter().filter(|&&i| i / 256 == bank as usize).count();
+
+        let highest = match indices.iter().max() {
+            Some(i) => format!("{:#04x}", i),
+            None => String::from("-"),
+        };
+
+        format!("{} assigned, highest {}, {} free in bank {}{}", self.tiles.len(), highest, 256 - used, bank, if self.dirty { " *unsaved*" } else { "" })
+    }
+
+    /// The tile numbers the charmap commands work on, in order: the assigned tiles of the selection,
+    /// or the one under the tile cursor.
+    fn charmap_targets(&self) -> Vec<usize> {
+        let positions = match (self.selection.is_empty(), self.tile_cursor) {
+            (false, _) => self.selection.clone(),
+            (true, Some(pos)) => std::iter::once(pos).collect(),
+            (true, None) => BTreeSet::new(),
+        };
+        self.tiles.iter().filter(|(_, pos)| positions.contains(pos)).filter_map(|(k, _)| parse_index(k)).collect::<BTreeSet<usize>>().into_iter().collect()
+    }
+
+    /// Gives a single tile all of `text`, or several tiles one character each. Empty text unmaps them.
+    fn set_characters(&mut self, targets: &[usize], text: &str) -> Result<(), String> {
+        if text.is_empty() {
+            for k in targets.iter() {
+                self.charmap.remove(k);
+            }
+        } else if targets.len() == 1 {
+            self.charmap.insert(targets[0], String::from(text));
+        } else {
+            let chars = text.chars().collect::<Vec<char>>();
+            if chars.len() != targets.len() {
+                return Err(format!("{} characters were given for {} tiles.", chars.len(), targets.len()));
+            }
+            for (k, c) in targets.iter().zip(chars) {
+                self.charmap.insert(*k, c.to_string());
+            }
+        }
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Copies the charmap as a ca65 `.charmap` block or a lookup table. Returns how many tiles it maps.
+    fn export_charmap(&mut self, ca65: bool) -> Result<usize, String> {
+        let assigned = self.tiles.keys().filter_map(|k| parse_index(k)).collect::<BTreeSet<usize>>();
+        let charmap = self.charmap.iter().filter(|(i, _)| assigned.contains(i)).map(|(&i, text)| (i, text.clone())).collect::<BTreeMap<usize, String>>();
+        if charmap.is_empty() {
+            return Err(String::from("No tiles have characters. Use Tools/Set Characters to give them some."));
+        }
+
+        let result = match ca65 {
+            true => charmap::ca65(&charmap, &self.prefix)?,
+            false => charmap::lookup_table(&charmap, &self.prefix)?,
+        };
+        println!("{}", result);
+        copy(&result);
+        Ok(charmap.len())
     }
 
     fn export_config(&mut self) {
+        let result = self.config_string();
+        println!("{}", result);
+        copy(&result);
+    }
+
+    fn config_string(&self) -> String {
         let keys = self.tiles.keys();
         let mut result = String::from("");
         for k in keys {
             result = format!("{},{}:{}_{}", result, k, self.tiles[k].0, self.tiles[k].1)
         }
-        println!("{}", result);
-        copy(&result);
+        result
     }
 
     fn set_tile(&mut self, tile: String, r: i32, c: i32) {
         self.clear_tile(r, c);
         self.tiles.insert(tile, (r, c));
+        self.dirty = true;
         // println!("Tiles: {:?}", self.tiles);
     }
 
@@ -315,81 +1771,289 @@ impl Model {
             }
         }
 
-        self.tiles.remove(&to_clear);
+        if self.tiles.remove(&to_clear).is_some() {
+            self.dirty = true;
+        }
     }
 }
 
-fn get_tile_in_picture(row: i32, col: i32, image: &PngImage) -> RgbImage {
-    let original = image.to_rgb_data();
-    let mut data = Vec::<u8>::new();
-    for i in 0..8 {
-        for j in 0..8 {
-            data.push(original[((row * 8 + i) * image.w() * 4 + (col * 8 + j) * 4) as usize]);
-            data.push(original[((row * 8 + i) * image.w() * 4 + (col * 8 + j) * 4 + 1) as usize]);
-            data.push(original[((row * 8 + i) * image.w() * 4 + (col * 8 + j) * 4 + 2) as usize]);
-            data.push(original[((row * 8 + i) * image.w() * 4 + (col * 8 + j) * 4 + 3) as usize]);
-        }
+fn save_file_chooser(message: &str, pattern: &str) -> Option<String> {
+    let mut chooser = FileChooser::new(".", pattern, FileChooserType::Create, message);
+    chooser.show();
+    while chooser.shown() {
+        wait();
     }
-    // println!("data: {:?}", data);
-    RgbImage::new(&data, 8, 8, ColorDepth::Rgba8).unwrap()
+    chooser.value(1)
 }
 
-fn tile_to_pattern(image: RgbImage) -> String {
-    let mut result = String::new();
-    let data = image.to_rgb_data();
-    let mut colors: BTreeSet<u8> = BTreeSet::new();
+fn choose_preview_palette() -> Option<usize> {
+    let names = chr::PREVIEW_PALETTES.iter().map(|x| x.0).collect::<Vec<&str>>();
+    match choice_default("Show the tiles with which palette?", names[0], names[1], names[2]) {
+        -1 => None,
+        x => Some(x as usize),
+    }
+}
 
-    for y in 0..image.h() {
-        for x in 0..image.w() {
-            colors.insert(data[(y * 4 * image.w() + x * 4) as usize].into());
+/// Shows each mismatched tile as it is on the sheet next to how its export decodes.
+fn show_mismatches(report: &str, mismatches: &[verify::Mismatch]) {
+    const SIZE: i32 = 64;
+    let mut win = Window::default().with_size(300, 400).with_label("Verify Export");
+    let mut flex = Flex::default().size_of_parent().column();
+    flex.set_margin(10);
+
+    let mut frame_report = Frame::default().with_label(report);
+    frame_report.set_align(Align::Inside | Align::Left | Align::Wrap);
+    flex.set_size(&mut frame_report, 60);
+
+    let mut header = Flex::default().row();
+    Frame::default().with_label("Tile");
+    Frame::default().with_label("Sheet");
+    Frame::default().with_label("Export");
+    header.end();
+    header.set_pad(10);
+    flex.set_size(&mut header, LABEL_HEIGHT);
+
+    let scroll = Scroll::default();
+    for (i, mismatch) in mismatches.iter().enumerate() {
+        let y = scroll.y() + i as i32 * (SIZE + 10);
+        Frame::new(scroll.x(), y, 80, SIZE, None).with_label(&mismatch.key);
+        for (j, pixels) in [Some(&mismatch.expected), mismatch.actual.as_ref()].iter().enumerate() {
+            let mut frame = Frame::new(scroll.x() + 90 + j as i32 * (SIZE + 20), y, SIZE, SIZE, None);
+            match pixels {
+                Some(pixels) => {
+                    let mut image = RgbImage::new(&pixels.concat(), 8, 8, ColorDepth::Rgb8).unwrap();
+                    image.scale(SIZE, SIZE, false, true);
+                    frame.set_image(Some(image));
+                    frame.set_frame(FrameType::BorderBox);
+                }
+                None => frame.set_label("missing"),
+            }
         }
     }
+    scroll.end();
+
+    flex.end();
+    win.end();
+    win.make_resizable(true);
+    win.show();
+}
 
+/// Asks how a binary export being verified was compressed.
+fn compression_dialog() -> Option<compress::Compression> {
+    let mut win = Window::default().with_size(320, 85).with_label("Verify Export");
+    let mut flex = Flex::default().size_of_parent().column();
+    flex.set_margin(10);
 
-    for i in 0..data.len() / 4 {
-        let byte = data[i * 4];
+    let row = Flex::default().row();
+    let _frame = Frame::default().with_label("Compression");
+    let mut compression = Choice::default();
+    for x in compress::Compression::ALL.iter() {
+        compression.add_choice(x.name());
+    }
+    compression.set_value(0);
+    row.end();
 
-        let val = colors.iter().position(|&r| r == byte).unwrap();
-        let temp = match val {
-            0 => "0",
-            1 => "1",
-            2 => "0",
-            3 => "1",
-            _ => panic!("Too many colors!")
-        };
+    let row = Flex::default().row();
+    let mut cancel = Button::default().with_label("Cancel");
+    let mut ok = Button::default().with_label("Verify");
+    row.end();
 
-        let mut temp = String::from(temp);
+    flex.end();
+    win.end();
+    win.make_modal(true);
+    win.show();
 
-        if i % 8 == 0 {
-            temp = format!("\n        .db     %{}", temp);
-        }
+    let accepted = Rc::new(RefCell::new(false));
+    let mut ok_win = win.clone();
+    let result = accepted.clone();
+    ok.set_callback(move |_| {
+        *result.borrow_mut() = true;
+        ok_win.hide();
+    });
+    let mut cancel_win = win.clone();
+    cancel.set_callback(move |_| cancel_win.hide());
+
+    while win.shown() {
+        wait();
+    }
 
-        result.push_str(&temp);
+    if !*accepted.borrow() {
+        return None;
     }
+    Some(compress::Compression::ALL[compression.value().max(0) as usize])
+}
 
-    for i in 0..data.len() / 4 {
-        let byte = data[i * 4];
+/// Asks which format to export tiles in, what to save them as and how to compress them.
+fn export_dialog() -> Option<(TileFormat, export::Output, compress::Compression)> {
+    let mut win = Window::default().with_size(360, 155).with_label("Export Tiles");
+    let mut flex = Flex::default().size_of_parent().column();
+    flex.set_margin(10);
 
-        let val = colors.iter().position(|&r| r == byte).unwrap();
-        let temp = match val {
-            0 => "0",
-            1 => "0",
-            2 => "1",
-            3 => "1",
-            _ => panic!("Too many colors!")
-        };
+    let row = Flex::default().row();
+    let _frame = Frame::default().with_label("Format");
+    let mut format = Choice::default();
+    for x in TileFormat::ALL.iter() {
+        format.add_choice(x.name());
+    }
+    format.set_value(0);
+    row.end();
+
+    let row = Flex::default().row();
+    let _frame = Frame::default().with_label("Output");
+    let mut output = Choice::default();
+    for x in export::Output::all() {
+        output.add_choice(x.name());
+    }
+    output.set_value(0);
+    row.end();
+
+    let row = Flex::default().row();
+    let _frame = Frame::default().with_label("Compression");
+    let mut compression = Choice::default();
+    for x in compress::Compression::ALL.iter() {
+        compression.add_choice(x.name());
+    }
+    compression.set_value(0);
+    row.end();
+
+    // Start each format off in the syntax its machine's assemblers use.
+    let mut format_output = output.clone();
+    format.set_callback(move |x| {
+        let syntax = export::Syntax::for_format(TileFormat::ALL[x.value().max(0) as usize]);
+        format_output.set_value(export::Syntax::ALL.iter().position(|&s| s == syntax).unwrap_or(0) as i32);
+    });
 
-        let mut temp = String::from(temp);
+    let row = Flex::default().row();
+    let mut cancel = Button::default().with_label("Cancel");
+    let mut ok = Button::default().with_label("Export");
+    row.end();
 
-        if i % 8 == 0 {
-            temp = format!("\n        .db     %{}", temp);
+    flex.end();
+    win.end();
+    win.make_modal(true);
+    win.show();
+
+    let accepted = Rc::new(RefCell::new(false));
+    let mut ok_win = win.clone();
+    let result = accepted.clone();
+    ok.set_callback(move |_| {
+        *result.borrow_mut() = true;
+        ok_win.hide();
+    });
+    let mut cancel_win = win.clone();
+    cancel.set_callback(move |_| cancel_win.hide());
+
+    while win.shown() {
+        wait();
+    }
+
+    if !*accepted.borrow() {
+        return None;
+    }
+    Some((TileFormat::ALL[format.value().max(0) as usize], export::Output::all()[output.value().max(0) as usize], compress::Compression::ALL[compression.value().max(0) as usize]))
+}
+
+/// Asks where in a file of `len` bytes the tiles are and how they are stored.
+/// Returns the offset, length, format and preview palette, or `None` when cancelled.
+fn chr_import_dialog(len: usize) -> Option<(usize, usize, TileFormat, usize)> {
+    let mut win = Window::default().with_size(320, 190).with_label("Open CHR");
+    let mut flex = Flex::default().size_of_parent().column();
+    flex.set_margin(10);
+
+    let row = Flex::default().row();
+    let _frame = Frame::default().with_label("Format");
+    let mut format = Choice::default();
+    for x in TileFormat::ALL.iter() {
+        format.add_choice(x.name());
+    }
+    format.set_value(0);
+    row.end();
+
+    let row = Flex::default().row();
+    let _frame = Frame::default().with_label("Offset");
+    let mut offset = Input::default();
+    offset.set_value("0");
+    row.end();
+
+    let row = Flex::default().row();
+    let _frame = Frame::default().with_label("Length");
+    let mut length = Input::default();
+    length.set_value(&format!("{}", len));
+    row.end();
+
+    let row = Flex::default().row();
+    let _frame = Frame::default().with_label("Palette");
+    let mut palette = Choice::default();
+    for x in chr::PREVIEW_PALETTES.iter() {
+        palette.add_choice(x.0);
+    }
+    palette.set_value(0);
+    row.end();
+
+    let row = Flex::default().row();
+    let mut cancel = Button::default().with_label("Cancel");
+    let mut ok = Button::default().with_label("Open");
+    row.end();
+
+    flex.end();
+    win.end();
+    win.make_modal(true);
+
+    let accepted = Rc::new(RefCell::new(None));
+    let mut ok_win = win.clone();
+    let result = accepted.clone();
+    ok.set_callback(move |_| {
+        *result.borrow_mut() = Some(true);
+        ok_win.hide();
+    });
+    let mut cancel_win = win.clone();
+    cancel.set_callback(move |_| cancel_win.hide());
+
+    // A field that does not parse is pointed out and the dialog shown again, rather than treated as a cancel.
+    loop {
+        win.show();
+        while win.shown() {
+            wait();
         }
 
-        result.push_str(&temp);
+        accepted.borrow_mut().take()?;
+        match (parse_number(&offset.value()), parse_number(&length.value())) {
+            (Some(offset), Some(length)) => {
+                return Some((offset, length, TileFormat::ALL[format.value().max(0) as usize], palette.value().max(0) as usize));
+            }
+            (None, _) => alert_default(&format!("The offset \"{}\" is not a number. Use decimal, or hex starting with 0x or $.", offset.value())),
+            (_, None) => alert_default(&format!("The length \"{}\" is not a number. Use decimal, or hex starting with 0x or $.", length.value())),
+        }
+    }
+}
+
+/// Parses a decimal number, or a hex one starting with `0x` or `$`.
+fn parse_number(text: &str) -> Option<usize> {
+    let text = text.trim();
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix('$')) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse::<usize>().ok(),
     }
+}
+
+fn recent_file() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+    Some(PathBuf::from(home).join(".lucifer-tile-editor-recent"))
+}
 
-    result
+fn load_recent() -> Vec<String> {
+    match recent_file().and_then(|file| fs::read_to_string(file).ok()) {
+        Some(content) => content.lines().filter(|x| !x.is_empty()).map(String::from).take(MAX_RECENT).collect(),
+        None => Vec::new(),
+    }
 }
+
+/// The tile at `(row, col)` as an image, blank when it lies outside the picture.
+fn get_tile_in_picture(row: i32, col: i32, image: &Sheet) -> RgbImage {
+    let tile = image.tile(row, col).unwrap_or_else(|_| vec![0; 8 * 8 * 4]);
+    RgbImage::new(&tile, 8, 8, ColorDepth::Rgba8).unwrap()
+}
+
 // use fltk::{image::*, app::*, browser::*, button::*, enums::*, input::*, prelude::*, window::*};
 // use fltk_theme::{WidgetTheme, ThemeType};
 // use fltk_flex::Flex;
@@ -0,0 +1,215 @@
+const IPS_MAX_OFFSET: usize = 0xff_ffff;
+const IPS_MAX_RECORD: usize = 0xffff;
+/// An IPS record may not start here, because its offset would read as the "EOF" marker.
+const IPS_EOF: usize = 0x45_4f46;
+
+/// Builds an IPS patch that turns `source` into `target`, which must be the same size.
+pub fn ips(source: &[u8], target: &[u8]) -> Result<Vec<u8>, String> {
+    if source.len() != target.len() {
+        return Err(String::from("IPS patches are only made between files of the same size."));
+    }
+
+    let mut patch = Vec::from(&b"PATCH"[..]);
+    for (mut start, end) in changed_runs(source, target) {
+        if start == IPS_EOF {
+            start -= 1;
+        }
+        if end > IPS_MAX_OFFSET {
+            return Err(String::from("IPS patches cannot reach past 16 MiB."));
+        }
+
+        for chunk_start in (start..end).step_by(IPS_MAX_RECORD) {
+            let chunk_end = (chunk_start + IPS_MAX_RECORD).min(end);
+            patch.extend_from_slice(&(chunk_start as u32).to_be_bytes()[1..]);
+            patch.extend_from_slice(&((chunk_end - chunk_start) as u16).to_be_bytes());
+            patch.extend_from_slice(&target[chunk_start..chunk_end]);
+        }
+    }
+    patch.extend_from_slice(b"EOF");
+    Ok(patch)
+}
+
+/// Builds a BPS patch that turns `source` into `target`, which must be the same size.
+pub fn bps(source: &[u8], target: &[u8]) -> Result<Vec<u8>, String> {
+    if source.len() != target.len() {
+        return Err(String::from("BPS patches are only made between files of the same size."));
+    }
+
+    let mut patch = Vec::from(&b"BPS1"[..]);
+    write_number(&mut patch, source.len() as u64);
+    write_number(&mut patch, target.len() as u64);
+    write_number(&mut patch, 0);
+
+    // Unchanged bytes are read from the source, changed ones are stored in the patch.
+    let mut output = 0;
+    for (start, end) in changed_runs(source, target) {
+        if start > output {
+            write_number(&mut patch, ((start - output - 1) << 2) as u64);
+        }
+        write_number(&mut patch, ((end - start - 1) << 2 | 1) as u64);
+        patch.extend_from_slice(&target[start..end]);
+        output = end;
+    }
+    if target.len() > output {
+        write_number(&mut patch, ((target.len() - output - 1) << 2) as u64);
+    }
+
+    patch.extend_from_slice(&crc32(source).to_le_bytes());
+    patch.extend_from_slice(&crc32(target).to_le_bytes());
+    let checksum = crc32(&patch);
+    patch.extend_from_slice(&checksum.to_le_bytes());
+    Ok(patch)
+}
+
+/// Returns the `(start, end)` byte ranges where `source` and `target` differ.
+fn changed_runs(source: &[u8], target: &[u8]) -> Vec<(usize, usize)> {
+    let mut runs = Vec::new();
+    let mut i = 0;
+    while i < target.len() {
+        if source[i] == target[i] {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < target.len() && source[i] != target[i] {
+            i += 1;
+        }
+        runs.push((start, i));
+    }
+    runs
+}
+
+fn write_number(patch: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let x = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            patch.push(0x80 | x);
+            break;
+        }
+        patch.push(x);
+        value -= 1;
+    }
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { crc >> 1 ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Applies an IPS patch the way patching tools do.
+    fn apply_ips(source: &[u8], patch: &[u8]) -> Vec<u8> {
+        assert_eq!(&patch[..5], b"PATCH");
+        let mut output = source.to_vec();
+        let mut i = 5;
+        while &patch[i..i + 3] != b"EOF" {
+            let offset = u32::from_be_bytes([0, patch[i], patch[i + 1], patch[i + 2]]) as usize;
+            let size = u16::from_be_bytes([patch[i + 3], patch[i + 4]]) as usize;
+            output[offset..offset + size].copy_from_slice(&patch[i + 5..i + 5 + size]);
+            i += 5 + size;
+        }
+        output
+    }
+
+    fn read_number(patch: &[u8], i: &mut usize) -> usize {
+        let (mut value, mut shift) = (0, 1);
+        loop {
+            let x = patch[*i] as usize;
+            *i += 1;
+            value += (x & 0x7f) * shift;
+            if x & 0x80 != 0 {
+                return value;
+            }
+            shift <<= 7;
+            value += shift;
+        }
+    }
+
+    /// Applies a BPS patch that only uses SourceRead and TargetRead, checking its checksums.
+    fn apply_bps(source: &[u8], patch: &[u8]) -> Vec<u8> {
+        assert_eq!(&patch[..4], b"BPS1");
+        let footer = patch.len() - 12;
+        assert_eq!(crc32(&patch[..footer + 8]).to_le_bytes(), patch[footer + 8..]);
+        assert_eq!(crc32(source).to_le_bytes(), patch[footer..footer + 4]);
+
+        let mut i = 4;
+        assert_eq!(read_number(patch, &mut i), source.len());
+        let size = read_number(patch, &mut i);
+        assert_eq!(read_number(patch, &mut i), 0);
+        let mut output = Vec::new();
+        while i < footer {
+            let command = read_number(patch, &mut i);
+            let length = (command >> 2) + 1;
+            match command & 3 {
+                0 => output.extend_from_slice(&source[output.len()..output.len() + length]),
+                1 => {
+                    output.extend_from_slice(&patch[i..i + length]);
+                    i += length;
+                }
+                _ => panic!("unexpected BPS command {}", command & 3),
+            }
+        }
+        assert_eq!(output.len(), size);
+        assert_eq!(crc32(&output).to_le_bytes(), patch[footer + 4..footer + 8]);
+        output
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn ips_reference() {
+        let source = [0; 8];
+        let target = [0, 0, 0xaa, 0xbb, 0, 0, 0, 0xcc];
+        let patch = ips(&source, &target).unwrap();
+        assert_eq!(patch, b"PATCH\x00\x00\x02\x00\x02\xaa\xbb\x00\x00\x07\x00\x01\xccEOF");
+        assert_eq!(apply_ips(&source, &patch), target);
+    }
+
+    #[test]
+    fn ips_avoids_eof_offset() {
+        let source = vec![0; IPS_EOF + 2];
+        let mut target = source.clone();
+        target[IPS_EOF] = 1;
+        let patch = ips(&source, &target).unwrap();
+        assert_eq!(&patch[5..8], &[0x45, 0x4f, 0x45]);
+        assert_eq!(apply_ips(&source, &patch), target);
+    }
+
+    #[test]
+    fn bps_reference() {
+        let source = [0, 0, 0, 0];
+        let target = [0, 1, 1, 0];
+        let patch = bps(&source, &target).unwrap();
+        // Sizes 4 and 4, no metadata, read 1 byte from the source, take 2 from the patch, read 1 more.
+        assert_eq!(patch[..12], *b"BPS1\x84\x84\x80\x80\x85\x01\x01\x80");
+        assert_eq!(apply_bps(&source, &patch), target);
+    }
+
+    #[test]
+    fn round_trips() {
+        let source = (0..20000).map(|x| (x * 31 % 256) as u8).collect::<Vec<u8>>();
+        let mut target = source.clone();
+        for i in (0..target.len()).step_by(97) {
+            target[i] ^= 0x5a;
+        }
+        for x in target[100..400].iter_mut() {
+            *x = !*x;
+        }
+        assert_eq!(apply_ips(&source, &ips(&source, &target).unwrap()), target);
+        assert_eq!(apply_bps(&source, &bps(&source, &target).unwrap()), target);
+        assert!(ips(&source, &target[1..]).is_err());
+    }
+}
@@ -0,0 +1,110 @@
+use std::fs::File;
+use std::io::BufWriter;
+
+/// An RGBA picture that is edited as a grid of 8x8 tiles.
+#[derive(Clone, Debug)]
+pub struct Sheet {
+    w: i32,
+    h: i32,
+    data: Vec<u8>,
+}
+
+impl Sheet {
+    /// Wraps raw pixels of `depth` bytes each: grey, grey and alpha, RGB or RGBA.
+    pub fn from_raw(w: i32, h: i32, depth: usize, raw: &[u8]) -> Sheet {
+        let mut data = Vec::with_capacity((w * h * 4) as usize);
+        // Expand grey and RGB pictures to RGBA so every tile has the same layout.
+        for pixel in raw.chunks(depth).take((w * h) as usize) {
+            match pixel.len() {
+                1 | 2 => data.extend_from_slice(&[pixel[0], pixel[0], pixel[0]]),
+                _ => data.extend_from_slice(&pixel[0..3]),
+            }
+            data.push(match pixel.len() {
+                2 | 4 => pixel[pixel.len() - 1],
+                _ => 255,
+            });
+        }
+        Sheet { w, h, data }
+    }
+
+    /// Lays out tiles of colour indices `per_row` to a row, coloured with `palette`.
+    /// Cells past the last tile are left in the first colour.
+    pub fn from_tiles(tiles: &[[u8; 64]], per_row: usize, palette: &[[u8; 3]]) -> Sheet {
+        let n_rows = tiles.len().div_ceil(per_row);
+        let w = per_row * 8;
+        let mut data = Vec::with_capacity(w * n_rows * 8 * 4);
+
+        for y in 0..n_rows * 8 {
+            for x in 0..w {
+                let index = match tiles.get((y / 8) * per_row + x / 8) {
+                    Some(tile) => tile[(y % 8) * 8 + x % 8] as usize,
+                    None => 0,
+                };
+                data.extend_from_slice(&palette[index % palette.len()]);
+                data.push(255);
+            }
+        }
+
+        Sheet { w: w as i32, h: (n_rows * 8) as i32, data }
+    }
+
+    /// Loads a PNG of any colour type and depth.
+    pub fn load_png(path: &str) -> Result<Sheet, String> {
+        let file = File::open(path).map_err(|e| format!("Could not load {}: {}", path, e))?;
+        let mut decoder = png::Decoder::new(file);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+
+        let mut reader = decoder.read_info().map_err(|e| format!("Could not load {}: {}", path, e))?;
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).map_err(|e| format!("Could not load {}: {}", path, e))?;
+        if info.width < 8 || info.height < 8 {
+            return Err(format!("{} is smaller than a single 8x8 tile.", path));
+        }
+        Ok(Sheet::from_raw(info.width as i32, info.height as i32, info.color_type.samples(), &data[..info.buffer_size()]))
+    }
+
+    pub fn w(&self) -> i32 {
+        self.w
+    }
+
+    pub fn h(&self) -> i32 {
+        self.h
+    }
+
+    pub fn save_png(&self, path: &str) -> Result<(), String> {
+        let file = File::create(path).map_err(|e| format!("Could not write {}: {}", path, e))?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.w as u32, self.h as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header().map_err(|e| format!("Could not write {}: {}", path, e))?;
+        writer.write_image_data(&self.data).map_err(|e| format!("Could not write {}: {}", path, e))
+    }
+
+    /// Returns the RGBA pixels of the tile at `(row, col)`, row by row.
+    pub fn tile(&self, row: i32, col: i32) -> Result<Vec<u8>, String> {
+        if row < 0 || col < 0 || row >= self.h / 8 || col >= self.w / 8 {
+            return Err(format!("Tile ({}, {}) is outside the {}x{} picture.", row, col, self.w, self.h));
+        }
+        let mut result = Vec::with_capacity(8 * 8 * 4);
+        for i in 0..8 {
+            let offset = (((row * 8 + i) * self.w + col * 8) * 4) as usize;
+            result.extend_from_slice(&self.data[offset..offset + 8 * 4]);
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_tiles_outside() {
+        let sheet = Sheet::from_tiles(&[[1; 64], [2; 64]], 2, &[[0, 0, 0], [0x10, 0x20, 0x30], [0xff, 0xff, 0xff]]);
+        assert_eq!(&sheet.tile(0, 1).unwrap()[..4], [0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(sheet.tile(1, 0).unwrap_err(), "Tile (1, 0) is outside the 16x8 picture.");
+        assert!(sheet.tile(0, 2).is_err());
+        assert!(sheet.tile(-1, 0).is_err());
+    }
+}
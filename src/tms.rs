@@ -0,0 +1,77 @@
+use std::collections::BTreeSet;
+use crate::chr;
+
+/// The fifteen TMS9918 colours, numbered from 1. Colour 0 is transparent.
+pub const PALETTE: [[u8; 3]; 15] = [
+    [0x00, 0x00, 0x00], [0x21, 0xc8, 0x42], [0x5e, 0xdc, 0x78], [0x54, 0x55, 0xed], [0x7d, 0x76, 0xfc],
+    [0xd4, 0x52, 0x4d], [0x42, 0xeb, 0xf5], [0xfc, 0x55, 0x54], [0xff, 0x79, 0x78], [0xd4, 0xc1, 0x54],
+    [0xe6, 0xce, 0x80], [0x21, 0xb0, 0x3b], [0xc9, 0x5b, 0xba], [0xcc, 0xcc, 0xcc], [0xff, 0xff, 0xff],
+];
+
+/// The TMS9918 colour number closest to an RGB colour.
+pub fn nearest(color: [u8; 3]) -> u8 {
+    chr::nearest((1..16).zip(PALETTE.iter().copied()), color)
+}
+
+fn rows(tile: &[u8]) -> Vec<Vec<u8>> {
+    tile.chunks(8 * 4).map(|row| row.chunks(4).map(|x| nearest([x[0], x[1], x[2]])).collect()).collect()
+}
+
+/// The rows of an RGBA tile with more than the two colours Graphics II allows, as a bit mask with row 0 in bit 0.
+pub fn row_violations(tile: &[u8]) -> u8 {
+    rows(tile).iter().enumerate()
+        .filter(|(_, row)| row.iter().collect::<BTreeSet<&u8>>().len() > 2)
+        .fold(0, |mask, (y, _)| mask | 1 << y)
+}
+
+/// Encodes an RGBA tile as its pattern and colour table entries. Each row's higher colour number
+/// is its foreground, set bits in the pattern, and the lower one its background.
+pub fn encode(tile: &[u8]) -> Result<([u8; 8], [u8; 8]), u8> {
+    let violations = row_violations(tile);
+    if violations != 0 {
+        return Err(violations);
+    }
+
+    let mut pattern = [0; 8];
+    let mut colors = [0; 8];
+    for (y, row) in rows(tile).iter().enumerate() {
+        let background = *row.iter().min().unwrap();
+        let foreground = *row.iter().max().unwrap();
+        for (x, &c) in row.iter().enumerate() {
+            if c == foreground && c != background {
+                pattern[y] |= 0x80 >> x;
+            }
+        }
+        colors[y] = foreground << 4 | background;
+    }
+    Ok((pattern, colors))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An RGBA tile with each pixel the given TMS9918 colour number.
+    fn tile(colors: &[u8; 64]) -> Vec<u8> {
+        colors.iter().flat_map(|&c| {
+            let [r, g, b] = PALETTE[c as usize - 1];
+            vec![r, g, b, 0xff]
+        }).collect()
+    }
+
+    #[test]
+    fn white_on_black() {
+        // The left half of each row is white, 15, on black, 1.
+        let mut colors = [1; 64];
+        colors.iter_mut().enumerate().filter(|(i, _)| i % 8 < 4).for_each(|(_, c)| *c = 15);
+        assert_eq!(encode(&tile(&colors)), Ok(([0xf0; 8], [0xf1; 8])));
+    }
+
+    #[test]
+    fn three_colors_in_a_row() {
+        let mut colors = [1; 64];
+        colors[3 * 8..3 * 8 + 3].copy_from_slice(&[1, 15, 7]);
+        assert_eq!(row_violations(&tile(&colors)), 1 << 3);
+        assert_eq!(encode(&tile(&colors)), Err(1 << 3));
+    }
+}
@@ -0,0 +1,313 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use crate::asm;
+use crate::chr::{self, TileFormat};
+use crate::compress::Compression;
+use crate::convert::{asm_patterns, parse_config, parse_index};
+use crate::ines::Rom;
+use crate::sheet::Sheet;
+
+/// A tile whose exported data does not decode back to the pixels on the sheet.
+#[derive(Clone, Debug)]
+pub struct Mismatch {
+    pub key: String,
+    /// The tile's pixels on the sheet, row by row.
+    pub expected: Vec<[u8; 3]>,
+    /// The exported tile decoded with the sheet palette, or `None` when the export left it out.
+    pub actual: Option<Vec<[u8; 3]>>,
+}
+
+/// What a check needs from a project: the sheet it names and the tiles assigned in it.
+#[derive(Clone, Debug)]
+pub struct Project {
+    pub sheet: Sheet,
+    pub tiles: BTreeMap<String, (i32, i32)>,
+    /// The prefix of the labels the ASM export writes.
+    pub prefix: String,
+    /// The palette of a decoded sheet, if the project gives one.
+    pub palette: Option<Vec<[u8; 3]>>,
+}
+
+impl Project {
+    /// Reads a project's `key=value` lines and loads the picture or ROM it names, as the editor opens it.
+    pub fn load(path: &str) -> Result<Project, String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+        let mut values = BTreeMap::new();
+        for line in content.lines() {
+            if let Some((key, value)) = line.split_once('=') {
+                values.insert(key.trim(), value.trim());
+            }
+        }
+
+        let mut palette = match values.get("palette") {
+            Some(x) => Some(parse_palette(x).ok_or(format!("{} has a malformed palette.", path))?),
+            None => None,
+        };
+        let sheet = match (values.get("image"), values.get("rom")) {
+            (Some(image), _) => Sheet::load_png(image)?,
+            (None, Some(rom)) => {
+                let data = fs::read(rom).map_err(|e| format!("Could not read {}: {}", rom, e))?;
+                let rom_data = Rom::parse(data).map_err(|e| format!("{}: {}", rom, e))?;
+                if rom_data.chr_size == 0 {
+                    return Err(format!("{} has no CHR-ROM; its graphics are in CHR-RAM.", rom));
+                }
+                let colors = palette.get_or_insert_with(|| chr::PREVIEW_PALETTES[0].1.to_vec());
+                Sheet::from_tiles(&chr::decode_tiles(rom_data.chr(), TileFormat::Nes), 16, colors)
+            }
+            (None, None) => return Err(format!("{} does not name a picture.", path)),
+        };
+
+        let tiles = parse_config(values.get("config").unwrap_or(&""), &sheet)?.into_iter().map(|(k, r, c)| (k, (r, c))).collect();
+        let prefix = values.get("prefix").unwrap_or(&"Tile_").to_string();
+        Ok(Project { sheet, tiles, prefix, palette })
+    }
+
+    /// The colours exported indices stand for: the palette of a decoded sheet, or every colour
+    /// of the assigned tiles of a picture in ascending red.
+    pub fn palette(&self) -> Vec<[u8; 3]> {
+        match &self.palette {
+            Some(palette) => palette.clone(),
+            None => picture_palette(&self.sheet, &self.tiles),
+        }
+    }
+
+    /// Checks an export of the project, as `read_export` reads it, or the ASM export as it would be
+    /// copied when there is no `path`. Returns the tiles that differ.
+    pub fn verify(&self, path: Option<&str>, compression: Compression) -> Result<Vec<Mismatch>, String> {
+        let exported = match path {
+            Some(path) => read_export(path, compression)?,
+            None => asm_export(&self.sheet, &self.tiles, &self.palette(), &self.prefix)?,
+        };
+        check(&self.sheet, &self.tiles, &exported, self.palette.as_deref())
+    }
+}
+
+/// Checks an export against the project at `path` for the command line, as `Project::verify` does.
+/// Returns a preview of each tile that differs followed by the summary, and whether every tile matched.
+pub fn check_project(path: &str, export: Option<&str>, compression: Compression) -> Result<(String, bool), String> {
+    let project = Project::load(path)?;
+    let mismatches = project.verify(export, compression)?;
+    let palette = project.palette();
+    let mut text = mismatches.iter().map(|x| format!("{}\n", preview(x, &palette))).collect::<String>();
+    text.push_str(&report(&mismatches, project.tiles.len()));
+    Ok((text, mismatches.is_empty()))
+}
+
+/// `check_project` for a command line tool, printing a preview of each mismatched tile. Returns the
+/// exit code: 0 when every tile matches, 1 when some differ and 2 when the check failed.
+/// `compression` names how a binary export is compressed, as in `Compression` ("rle", "lz4" and so on).
+pub fn run_cli(project: &str, export: Option<&str>, compression: Option<&str>) -> i32 {
+    let compression = match compression {
+        Some(name) => match Compression::ALL.iter().find(|x| format!("{:?}", x).eq_ignore_ascii_case(name)) {
+            Some(&compression) => compression,
+            None => {
+                eprintln!("Unknown compression \"{}\".", name);
+                return 2;
+            }
+        },
+        None => Compression::None,
+    };
+    match check_project(project, export, compression) {
+        Ok((text, matched)) => {
+            println!("{}", text);
+            if matched { 0 } else { 1 }
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            2
+        }
+    }
+}
+
+/// Reads a palette written as `rrggbb` colours separated by commas.
+pub fn parse_palette(text: &str) -> Option<Vec<[u8; 3]>> {
+    text.split(',').map(|x| {
+        let x = u32::from_str_radix(x.trim().trim_start_matches('#'), 16).ok()?;
+        Some([(x >> 16) as u8, (x >> 8) as u8, x as u8])
+    }).collect()
+}
+
+/// Decodes NES tiles, keyed by tile number, from an exported `.asm` file, a ROM or a CHR binary
+/// compressed with `compression`. An `.asm` file's header says how it is compressed, so `compression`
+/// is not needed for one.
+pub fn read_export(path: &str, compression: Compression) -> Result<BTreeMap<usize, [u8; 64]>, String> {
+    let lower = path.to_lowercase();
+    let data = fs::read(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+    let decompress = |compression: Compression, data: &[u8]| {
+        let data = compression.decompress(data).map_err(|e| format!("{} is not {} data: {}", path, compression.name(), e))?;
+        Ok(chr::decode_tiles(&data, TileFormat::Nes).into_iter().enumerate().collect())
+    };
+
+    if [".asm", ".s", ".inc"].iter().any(|x| lower.ends_with(x)) {
+        let text = String::from_utf8_lossy(&data);
+        match Compression::ALL.iter().find(|x| text.contains(&format!(" Tiles are compressed with {}.", x.name()))) {
+            // The compressed tiles are one byte table, read back a byte at a time.
+            Some(&compression) => {
+                let parsed = asm::parse_patterns(&text, 1).map_err(|e| format!("{}: {}", path, e))?;
+                decompress(compression, &parsed.iter().map(|x| x.bytes[0]).collect::<Vec<u8>>())
+            }
+            None => {
+                let parsed = asm::parse_patterns(&text, TileFormat::Nes.tile_bytes()).map_err(|e| format!("{}: {}", path, e))?;
+                Ok(parsed.iter().map(|x| (x.index, TileFormat::Nes.decode(&x.bytes))).collect())
+            }
+        }
+    } else if lower.ends_with(".nes") {
+        let rom = Rom::parse(data).map_err(|e| format!("{}: {}", path, e))?;
+        Ok(chr::decode_tiles(rom.chr(), TileFormat::Nes).into_iter().enumerate().collect())
+    } else {
+        decompress(compression, &data)
+    }
+}
+
+/// Decodes the ASM export of `tiles` in `sheet` as it would be copied, numbered against `palette`.
+pub fn asm_export(sheet: &Sheet, tiles: &BTreeMap<String, (i32, i32)>, palette: &[[u8; 3]], prefix: &str) -> Result<BTreeMap<usize, [u8; 64]>, String> {
+    let text = asm_patterns(sheet, tiles, palette, TileFormat::Nes, prefix)?;
+    Ok(asm::parse_patterns(&text, TileFormat::Nes.tile_bytes())?.iter().map(|x| (x.index, TileFormat::Nes.decode(&x.bytes))).collect())
+}
+
+/// Compares the tiles assigned in `sheet` with the decoded export, returning those that differ.
+/// `palette` is that of a decoded sheet. A picture has none, and is read back with `picture_palette`.
+pub fn check(sheet: &Sheet, tiles: &BTreeMap<String, (i32, i32)>, exported: &BTreeMap<usize, [u8; 64]>, palette: Option<&[[u8; 3]]>) -> Result<Vec<Mismatch>, String> {
+    let palette = palette.map(|x| x.to_vec()).unwrap_or_else(|| picture_palette(sheet, tiles));
+    let mut mismatches = Vec::new();
+    for (k, &(r, c)) in tiles.iter() {
+        let i = parse_index(k).ok_or(format!("{} is not a tile number.", k))?;
+        let tile = sheet.tile(r, c).map_err(|e| format!("{}: {}", k, e))?;
+        if let Some(mismatch) = compare(k, &tile, exported.get(&i), &palette) {
+            mismatches.push(mismatch);
+        }
+    }
+    Ok(mismatches)
+}
+
+/// The colours used by RGBA `tiles` in ascending red. This is the palette a picture's tiles are
+/// numbered against when exported and read back with.
+pub fn sheet_palette<'a>(tiles: impl Iterator<Item = &'a [u8]>) -> Vec<[u8; 3]> {
+    let mut colors = BTreeSet::new();
+    for tile in tiles {
+        for pixel in tile.chunks(4) {
+            colors.insert([pixel[0], pixel[1], pixel[2]]);
+        }
+    }
+    colors.into_iter().collect()
+}
+
+/// The colours of the tiles assigned in a picture, in ascending red.
+pub fn picture_palette(sheet: &Sheet, tiles: &BTreeMap<String, (i32, i32)>) -> Vec<[u8; 3]> {
+    let tiles = tiles.values().filter_map(|&(r, c)| sheet.tile(r, c).ok()).collect::<Vec<Vec<u8>>>();
+    sheet_palette(tiles.iter().map(|x| x.as_slice()))
+}
+
+/// Compares the RGBA pixels of a tile with exported colour indices looked up in `palette`.
+pub fn compare(key: &str, expected: &[u8], actual: Option<&[u8; 64]>, palette: &[[u8; 3]]) -> Option<Mismatch> {
+    let expected = expected.chunks(4).map(|x| [x[0], x[1], x[2]]).collect::<Vec<[u8; 3]>>();
+    // Indices past the palette come out magenta, which no sheet is expected to contain.
+    let actual = actual.map(|x| x.iter().map(|&i| *palette.get(i as usize).unwrap_or(&[0xff, 0x00, 0xff])).collect::<Vec<[u8; 3]>>());
+
+    if actual.as_ref() == Some(&expected) {
+        return None;
+    }
+    Some(Mismatch { key: String::from(key), expected, actual })
+}
+
+/// Draws a mismatch as text, the sheet on the left and the export on the right, with each pixel
+/// shown as its index in `palette`, `?` for colours outside it and `.` for a missing tile.
+pub fn preview(mismatch: &Mismatch, palette: &[[u8; 3]]) -> String {
+    let symbol = |color: &[u8; 3]| match palette.iter().position(|x| x == color) {
+        Some(i) if i < 36 => std::char::from_digit(i as u32, 36).unwrap(),
+        _ => '?',
+    };
+
+    let mut result = format!("{}\n    {:<8}  {}\n", mismatch.key, "sheet", "export");
+    for y in 0..8 {
+        let expected = mismatch.expected[y * 8..y * 8 + 8].iter().map(symbol).collect::<String>();
+        let actual = match &mismatch.actual {
+            Some(actual) => actual[y * 8..y * 8 + 8].iter().map(symbol).collect::<String>(),
+            None => String::from("........"),
+        };
+        result.push_str(&format!("    {}  {}\n", expected, actual));
+    }
+    result
+}
+
+/// Sums up a verification of `checked` tiles.
+pub fn report(mismatches: &[Mismatch], checked: usize) -> String {
+    match mismatches.len() {
+        0 => format!("All {} tiles match the sheet.", checked),
+        n => {
+            let keys = mismatches.iter().map(|x| x.key.as_str()).collect::<Vec<&str>>();
+            format!("{} of {} tiles do not match the sheet: {}.", n, checked, keys.join(", "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_asm_export() {
+        let palette = [[0, 0, 0], [0x40, 0, 0], [0x80, 0, 0], [0xc0, 0, 0]];
+        let tiles = (0..3).map(|i| {
+            let mut tile = [0; 64];
+            tile.iter_mut().enumerate().for_each(|(x, p)| *p = ((x + i) % 4) as u8);
+            tile
+        }).collect::<Vec<[u8; 64]>>();
+        let sheet = Sheet::from_tiles(&tiles, 16, &palette);
+        let assigned = (0..3).map(|i| (format!("{:#04x}", i), (0, i))).collect::<BTreeMap<String, (i32, i32)>>();
+
+        let mut exported = asm_export(&sheet, &assigned, &palette, "Tile_").unwrap();
+        assert!(check(&sheet, &assigned, &exported, Some(&palette)).unwrap().is_empty());
+
+        exported.remove(&1);
+        let mismatches = check(&sheet, &assigned, &exported, Some(&palette)).unwrap();
+        assert_eq!(mismatches.iter().map(|x| x.key.as_str()).collect::<Vec<&str>>(), ["0x01"]);
+        assert!(mismatches[0].actual.is_none());
+    }
+
+    #[test]
+    fn reads_compressed_exports() {
+        use crate::export::{self, Export, Syntax};
+
+        let tiles = (0..3).map(|i| (i, (0..16).map(|x| (x / 4 * (i + 1)) as u8).collect::<Vec<u8>>())).collect::<BTreeMap<usize, Vec<u8>>>();
+        let decoded = tiles.iter().map(|(&i, x)| (i, TileFormat::Nes.decode(x))).collect::<BTreeMap<usize, [u8; 64]>>();
+        let dir = std::env::temp_dir();
+
+        for compression in [Compression::Rle, Compression::Lz4] {
+            let mut export = Export { tiles: tiles.clone(), ..Default::default() };
+            export.compress(compression, TileFormat::Nes);
+            for syntax in [Syntax::Db, Syntax::Ca65] {
+                let path = dir.join(format!("lucifer-verify-{:?}-{:?}.asm", compression, syntax)).to_string_lossy().into_owned();
+                fs::write(&path, export::text(&export, TileFormat::Nes, syntax, "Tile_").unwrap()).unwrap();
+                assert_eq!(read_export(&path, Compression::None).unwrap(), decoded, "{:?} {:?}", compression, syntax);
+                fs::remove_file(&path).unwrap();
+            }
+
+            let path = dir.join(format!("lucifer-verify-{:?}.chr", compression)).to_string_lossy().into_owned();
+            fs::write(&path, export.tile_data(TileFormat::Nes)).unwrap();
+            assert_eq!(read_export(&path, compression).unwrap(), decoded, "{:?}", compression);
+            fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn catches_tiles_numbered_on_their_own() {
+        let palette = [[0, 0, 0], [0x40, 0, 0], [0x80, 0, 0], [0xc0, 0, 0]];
+        let mut first = [0; 64];
+        first.iter_mut().enumerate().for_each(|(x, p)| *p = (x % 4) as u8);
+        // The second tile uses the picture's colours 2 and 3, exported as if they were its own 0 and 1.
+        let mut second = [2; 64];
+        second.iter_mut().skip(32).for_each(|p| *p = 3);
+        let sheet = Sheet::from_tiles(&[first, second], 16, &palette);
+        let assigned = (0..2).map(|i| (format!("{:#04x}", i), (0, i))).collect::<BTreeMap<String, (i32, i32)>>();
+
+        let mut exported = BTreeMap::new();
+        exported.insert(0, first);
+        exported.insert(1, second.map(|x| x - 2));
+        for palette in [None, Some(&palette[..])].iter() {
+            let mismatches = check(&sheet, &assigned, &exported, *palette).unwrap();
+            assert_eq!(mismatches.len(), 1);
+            assert_eq!(mismatches[0].key, "0x01");
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::BTreeMap;
+use crate::chr;
+
+/// The Spectrum's colours as (colour number, bright), with black counted as normal.
+/// Normal colours are at 0xd7 and bright ones at full intensity.
+fn palette() -> Vec<(u8, bool, [u8; 3])> {
+    let mut colors = Vec::new();
+    for bright in [false, true] {
+        let level = if bright { 0xff } else { 0xd7 };
+        for i in 0..8u8 {
+            // Colour numbers hold blue in bit 0, red in bit 1 and green in bit 2.
+            let color = [if i & 2 != 0 { level } else { 0 }, if i & 4 != 0 { level } else { 0 }, if i & 1 != 0 { level } else { 0 }];
+            if !(bright && i == 0) {
+                colors.push((i, bright, color));
+            }
+        }
+    }
+    colors
+}
+
+/// The Spectrum colour closest to an RGB colour, as its number and whether it is bright.
+pub fn nearest(color: [u8; 3]) -> (u8, bool) {
+    chr::nearest(palette().into_iter().map(|(i, bright, x)| ((i, bright), x)), color)
+}
+
+/// Counts each Spectrum colour in an RGBA tile.
+fn colors(tile: &[u8]) -> BTreeMap<(u8, bool), usize> {
+    let mut counts = BTreeMap::new();
+    for x in tile.chunks(4) {
+        *counts.entry(nearest([x[0], x[1], x[2]])).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Whether a tile needs more than one attribute: over two colours, or bright and normal ones together.
+/// Black goes with either.
+pub fn clash(tile: &[u8]) -> bool {
+    let counts = colors(tile);
+    let bright = counts.keys().filter(|(i, _)| *i != 0).map(|&(_, bright)| bright).collect::<Vec<bool>>();
+    counts.len() > 2 || bright.windows(2).any(|x| x[0] != x[1])
+}
+
+/// Encodes an RGBA tile as its bitmap and attribute byte. The more common colour is the paper and the
+/// other the ink, set bits in the bitmap. Flash is never set. Returns `None` for a clashing tile.
+pub fn encode(tile: &[u8]) -> Option<([u8; 8], u8)> {
+    if clash(tile) {
+        return None;
+    }
+
+    let counts = colors(tile);
+    let mut ranked = counts.iter().collect::<Vec<(&(u8, bool), &usize)>>();
+    ranked.sort_by_key(|(_, &n)| std::cmp::Reverse(n));
+    let paper = *ranked[0].0;
+    let ink = ranked.get(1).map(|x| *x.0).unwrap_or(paper);
+    let bright = paper.1 || ink.1;
+
+    let mut bitmap = [0; 8];
+    for (i, x) in tile.chunks(4).enumerate() {
+        if ink != paper && nearest([x[0], x[1], x[2]]) == ink {
+            bitmap[i / 8] |= 0x80 >> (i % 8);
+        }
+    }
+    Some((bitmap, (bright as u8) << 6 | paper.0 << 3 | ink.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An RGBA tile with its first `n` pixels `ink` and the rest `paper`.
+    fn tile(ink: [u8; 3], n: usize, paper: [u8; 3]) -> Vec<u8> {
+        (0..64).flat_map(|i| {
+            let [r, g, b] = if i < n { ink } else { paper };
+            vec![r, g, b, 0xff]
+        }).collect()
+    }
+
+    #[test]
+    fn attributes() {
+        // Bright yellow ink, 6, on bright blue paper, 1.
+        let (bitmap, attribute) = encode(&tile([0xff, 0xff, 0x00], 16, [0x00, 0x00, 0xff])).unwrap();
+        assert_eq!(bitmap, [0xff, 0xff, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(attribute, 0x4e);
+
+        // White ink, 7, on black paper, not bright.
+        let (bitmap, attribute) = encode(&tile([0xd7, 0xd7, 0xd7], 4, [0x00, 0x00, 0x00])).unwrap();
+        assert_eq!(bitmap, [0xf0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(attribute, 0x07);
+    }
+
+    #[test]
+    fn three_colors_clash() {
+        let mut three = tile([0xd7, 0x00, 0x00], 8, [0x00, 0x00, 0x00]);
+        three[8 * 4..8 * 4 + 3].copy_from_slice(&[0x00, 0xd7, 0x00]);
+        assert!(clash(&three));
+        assert_eq!(encode(&three), None);
+
+        // Bright and normal colours cannot share a cell either, but black goes with both.
+        assert!(clash(&tile([0xff, 0x00, 0x00], 8, [0x00, 0xd7, 0x00])));
+        assert!(!clash(&tile([0xff, 0x00, 0x00], 8, [0x00, 0x00, 0x00])));
+    }
+}